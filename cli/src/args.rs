@@ -13,6 +13,8 @@ pub enum Command {
     /// Blind merged mining commands.
     #[command(subcommand)]
     Bmm(Bmm),
+    /// Show the pending withdrawal bundle and its acks on mainchain.
+    PendingBundle,
 }
 
 #[derive(Debug, Subcommand)]
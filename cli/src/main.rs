@@ -4,7 +4,7 @@ use anyhow::Result;
 use args::{Bmm, Cli, Command};
 use bitnames_api::bit_names_client::BitNamesClient;
 use bitnames_api::*;
-use bitnames_types::bitcoin;
+use bitnames_types::{bitcoin, WithdrawalBundle, WithdrawalBundleScore};
 use clap::Parser;
 use ureq_jsonrpc::json;
 
@@ -15,6 +15,31 @@ async fn main() -> Result<()> {
     let args = Cli::parse();
     match args.command {
         Command::Bmm(command) => bmm(command, &mut client).await?,
+        Command::PendingBundle => pending_bundle(&mut client).await?,
+    }
+    Ok(())
+}
+
+async fn pending_bundle(
+    client: &mut BitNamesClient<bitnames_api::tonic::transport::Channel>,
+) -> Result<()> {
+    let request = tonic::Request::new(GetPendingBundleRequest {});
+    let response = client.get_pending_bundle(request).await?;
+    let bundle: Option<(WithdrawalBundle, Option<WithdrawalBundleScore>)> =
+        bincode::deserialize(&response.into_inner().bundle)?;
+    match bundle {
+        Some((bundle, score)) => {
+            println!("txid: {}", bundle.transaction.txid());
+            println!("withdrawals: {}", bundle.spent_utxos.len());
+            match score {
+                Some(score) => {
+                    println!("blocks left: {}", score.blocks_left);
+                    println!("work score: {}", score.work_score);
+                }
+                None => println!("not known to mainchain yet"),
+            }
+        }
+        None => println!("no pending withdrawal bundle"),
     }
     Ok(())
 }
@@ -8,7 +8,6 @@ use std::collections::HashMap;
 use std::str::FromStr;
 use tokio::sync::{mpsc, oneshot};
 
-const BLOCK_SIZE_LIMIT: usize = 100 * 1024;
 const THIS_SIDECHAIN: usize = 0;
 
 pub struct Drivechain {
@@ -68,16 +67,24 @@ impl Drivechain {
         Ok(self.client.getbestblockhash().await?)
     }
 
+    /// Deposits after `start` up to `end`, and the status of the
+    /// `pending_bundle`.
     pub async fn get_two_way_peg_data(
         &mut self,
         end: bitcoin::BlockHash,
         start: Option<bitcoin::BlockHash>,
+        pending_bundle: Option<bitcoin::Txid>,
     ) -> Result<TwoWayPegData> {
-        let (deposits, deposit_block_hash) = self.get_deposit_outputs(end, start).await?;
-        let bundle_statuses = self.get_withdrawal_bundle_statuses().await?;
+        let (deposits, deposit_block) = self.get_deposit_outputs(end, start).await?;
+        let mut bundle_statuses = HashMap::new();
+        if let Some(txid) = pending_bundle {
+            if let Some(status) = self.get_withdrawal_bundle_status(txid).await? {
+                bundle_statuses.insert(txid, status);
+            }
+        }
         let two_way_peg_data = TwoWayPegData {
             deposits,
-            deposit_block_hash,
+            deposit_block,
             bundle_statuses,
         };
         Ok(two_way_peg_data)
@@ -95,11 +102,29 @@ impl Drivechain {
         Ok(())
     }
 
+    /// Voting progress of the bundle `txid`, `None` if mainchain doesn't
+    /// know about it.
+    pub async fn get_withdrawal_bundle_score(
+        &self,
+        txid: bitcoin::Txid,
+    ) -> Result<Option<WithdrawalBundleScore>> {
+        let statuses = self.client.listwithdrawalstatus(THIS_SIDECHAIN).await?;
+        let score = statuses
+            .iter()
+            .find(|status| status.hash == txid)
+            .map(|status| WithdrawalBundleScore {
+                txid,
+                blocks_left: status.nblocksleft as u32,
+                work_score: status.nworkscore as u32,
+            });
+        Ok(score)
+    }
+
     async fn get_deposit_outputs(
         &mut self,
         end: bitcoin::BlockHash,
         start: Option<bitcoin::BlockHash>,
-    ) -> Result<(HashMap<OutPoint, Output>, Option<bitcoin::BlockHash>)> {
+    ) -> Result<(HashMap<OutPoint, Output>, Option<(bitcoin::BlockHash, u32)>)> {
         let deposits = self
             .client
             .listsidechaindepositsbyblock(THIS_SIDECHAIN, Some(end), start)
@@ -132,21 +157,43 @@ impl Drivechain {
             last_total = total;
             last_block_hash = Some(deposit.hashblock);
         }
-        Ok((outputs, last_block_hash))
+        let deposit_block = match last_block_hash {
+            Some(block_hash) => {
+                let block = self.client.getblock(&block_hash, None).await?;
+                Some((block_hash, block.height as u32))
+            }
+            None => None,
+        };
+        Ok((outputs, deposit_block))
     }
-    async fn get_withdrawal_bundle_statuses(
+    /// Status of the bundle `txid`, `None` while it is neither spent nor
+    /// failed.
+    async fn get_withdrawal_bundle_status(
         &mut self,
-    ) -> Result<HashMap<bitcoin::Txid, WithdrawalBundleStatus>> {
-        let mut statuses = HashMap::new();
-        for spent in &self.client.listspentwithdrawals().await? {
-            if spent.nsidechain == THIS_SIDECHAIN {
-                statuses.insert(spent.hash, WithdrawalBundleStatus::Confirmed);
-            }
-        }
-        for failed in &self.client.listfailedwithdrawals().await? {
-            statuses.insert(failed.hash, WithdrawalBundleStatus::Failed);
+        txid: bitcoin::Txid,
+    ) -> Result<Option<WithdrawalBundleStatus>> {
+        // Mainchain lists every spent and failed bundle, only look up the
+        // block of the one we are interested in.
+        let spent = self
+            .client
+            .listspentwithdrawals()
+            .await?
+            .into_iter()
+            .find(|spent| spent.nsidechain == THIS_SIDECHAIN && spent.hash == txid);
+        if let Some(spent) = spent {
+            let block = self.client.getblock(&spent.hashblock, None).await?;
+            let main_block_height = block.height as u32;
+            return Ok(Some(WithdrawalBundleStatus::Confirmed {
+                main_block_height,
+            }));
         }
-        Ok(statuses)
+        let failed = self
+            .client
+            .listfailedwithdrawals()
+            .await?
+            .iter()
+            .any(|failed| failed.hash == txid);
+        Ok(failed.then_some(WithdrawalBundleStatus::Failed))
     }
 
     pub fn new() -> Result<Self> {
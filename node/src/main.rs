@@ -1,13 +1,16 @@
 mod amount;
 mod drivechain;
 mod mainchain_client;
+mod mempool;
 mod node;
 
 use anyhow::Result;
+use bitcoin::hashes::Hash as _;
 use bitnames_api::bit_names_server::{BitNames, BitNamesServer};
 use bitnames_api::*;
 use bitnames_state::Body;
 use bitnames_state::*;
+use clap::Parser;
 use core::str::FromStr;
 use futures::executor::block_on;
 use std::sync::Mutex;
@@ -20,9 +23,27 @@ struct BitNamesNode {
     // libp2p -- event sink
 }
 
+#[derive(Debug, Parser)]
+#[clap(author, version, about)]
+struct Cli {
+    /// Start from a state snapshot instead of the local database.
+    #[arg(long)]
+    snapshot: Option<std::path::PathBuf>,
+    /// Index confirmed transactions by txid.
+    #[arg(long)]
+    txindex: bool,
+    /// Address that receives fees of blocks produced by this node.
+    #[arg(long)]
+    payout_address: Option<Address>,
+}
+
 impl BitNamesNode {
-    async fn new() -> Result<BitNamesNode> {
-        let node = Mutex::new(node::Node::new()?);
+    async fn new(cli: &Cli) -> Result<BitNamesNode> {
+        let node = Mutex::new(node::Node::new(
+            cli.snapshot.as_deref(),
+            cli.txindex,
+            cli.payout_address,
+        )?);
         Ok(BitNamesNode { node })
     }
 }
@@ -54,19 +75,220 @@ impl BitNames for BitNamesNode {
         Ok(Response::new(GetUtxosByAddressesResponse { utxos }))
     }
 
+    async fn get_key_history(
+        &self,
+        request: Request<GetKeyHistoryRequest>,
+    ) -> Result<Response<GetKeyHistoryResponse>, Status> {
+        let request = request.into_inner();
+        let key: Key = parse_hash("key", request.key)?;
+        let entries = self
+            .node
+            .lock()
+            .unwrap()
+            .get_key_history(&key, request.start_height, request.limit as usize)
+            .map_err(internal_error)?
+            .iter()
+            .map(bincode::serialize)
+            .collect::<Result<_, _>>()
+            .map_err(internal_error)?;
+        Ok(Response::new(GetKeyHistoryResponse { entries }))
+    }
+
+    async fn get_records(
+        &self,
+        request: Request<GetRecordsRequest>,
+    ) -> Result<Response<GetRecordsResponse>, Status> {
+        let key: Key = parse_hash("key", request.into_inner().key)?;
+        let records = self
+            .node
+            .lock()
+            .unwrap()
+            .get_records(&key)
+            .map_err(internal_error)?
+            .iter()
+            .map(bincode::serialize)
+            .collect::<Result<_, _>>()
+            .map_err(internal_error)?;
+        Ok(Response::new(GetRecordsResponse { records }))
+    }
+
+    async fn get_name_proof(
+        &self,
+        request: Request<GetNameProofRequest>,
+    ) -> Result<Response<GetNameProofResponse>, Status> {
+        let key: Key = parse_hash("key", request.into_inner().key)?;
+        let proof = self
+            .node
+            .lock()
+            .unwrap()
+            .get_name_proof(&key)
+            .map_err(internal_error)?;
+        let proof = bincode::serialize(&proof).map_err(internal_error)?;
+        Ok(Response::new(GetNameProofResponse { proof }))
+    }
+
+    async fn list_withdrawal_bundles(
+        &self,
+        _request: Request<ListWithdrawalBundlesRequest>,
+    ) -> Result<Response<ListWithdrawalBundlesResponse>, Status> {
+        let bundles = self
+            .node
+            .lock()
+            .unwrap()
+            .get_withdrawal_bundles()
+            .map_err(internal_error)?
+            .iter()
+            .map(bincode::serialize)
+            .collect::<Result<_, _>>()
+            .map_err(internal_error)?;
+        Ok(Response::new(ListWithdrawalBundlesResponse { bundles }))
+    }
+
+    async fn get_withdrawal_status(
+        &self,
+        request: Request<GetWithdrawalStatusRequest>,
+    ) -> Result<Response<GetWithdrawalStatusResponse>, Status> {
+        let outpoint: OutPoint = bincode::deserialize(&request.into_inner().outpoint)
+            .map_err(|err| Status::invalid_argument(format!("invalid outpoint: {err}")))?;
+        let status = self
+            .node
+            .lock()
+            .unwrap()
+            .get_withdrawal_status(&outpoint)
+            .map_err(internal_error)?;
+        let status = bincode::serialize(&status).map_err(internal_error)?;
+        Ok(Response::new(GetWithdrawalStatusResponse { status }))
+    }
+
+    async fn get_pending_bundle(
+        &self,
+        _request: Request<GetPendingBundleRequest>,
+    ) -> Result<Response<GetPendingBundleResponse>, Status> {
+        let bundle = self
+            .node
+            .lock()
+            .unwrap()
+            .get_pending_bundle()
+            .map_err(internal_error)?;
+        let bundle = bincode::serialize(&bundle).map_err(internal_error)?;
+        Ok(Response::new(GetPendingBundleResponse { bundle }))
+    }
+
+    async fn get_block(
+        &self,
+        request: Request<GetBlockRequest>,
+    ) -> Result<Response<GetBlockResponse>, Status> {
+        let height = request.into_inner().height;
+        let block = self
+            .node
+            .lock()
+            .unwrap()
+            .get_block(height)
+            .map_err(internal_error)?;
+        let block = bincode::serialize(&block).map_err(internal_error)?;
+        Ok(Response::new(GetBlockResponse { block }))
+    }
+
+    async fn get_header(
+        &self,
+        request: Request<GetHeaderRequest>,
+    ) -> Result<Response<GetHeaderResponse>, Status> {
+        let height = request.into_inner().height;
+        let header = self
+            .node
+            .lock()
+            .unwrap()
+            .get_header(height)
+            .map_err(internal_error)?;
+        let header = bincode::serialize(&header).map_err(internal_error)?;
+        Ok(Response::new(GetHeaderResponse { header }))
+    }
+
+    async fn get_block_by_hash(
+        &self,
+        request: Request<GetBlockByHashRequest>,
+    ) -> Result<Response<GetBlockByHashResponse>, Status> {
+        let block_hash: BlockHash = parse_hash("block_hash", request.into_inner().block_hash)?;
+        let block = self
+            .node
+            .lock()
+            .unwrap()
+            .get_block_by_hash(&block_hash)
+            .map_err(internal_error)?;
+        let block = bincode::serialize(&block).map_err(internal_error)?;
+        Ok(Response::new(GetBlockByHashResponse { block }))
+    }
+
+    async fn get_chain_info(
+        &self,
+        _request: Request<GetChainInfoRequest>,
+    ) -> Result<Response<GetChainInfoResponse>, Status> {
+        let (height, block_hash, main_block_hash) = self
+            .node
+            .lock()
+            .unwrap()
+            .get_chain_info()
+            .map_err(internal_error)?;
+        let block_hash: [u8; 32] = block_hash.into();
+        Ok(Response::new(GetChainInfoResponse {
+            height,
+            block_hash: block_hash.to_vec(),
+            main_block_hash: main_block_hash.into_inner().to_vec(),
+        }))
+    }
+
+    async fn get_transaction(
+        &self,
+        request: Request<GetTransactionRequest>,
+    ) -> Result<Response<GetTransactionResponse>, Status> {
+        let txid: Txid = parse_hash("txid", request.into_inner().txid)?;
+        let transaction = self
+            .node
+            .lock()
+            .unwrap()
+            .get_transaction(&txid)
+            .map_err(internal_error)?;
+        let transaction = bincode::serialize(&transaction).map_err(internal_error)?;
+        Ok(Response::new(GetTransactionResponse { transaction }))
+    }
+
+    async fn resolve_name_at(
+        &self,
+        request: Request<ResolveNameAtRequest>,
+    ) -> Result<Response<ResolveNameAtResponse>, Status> {
+        let request = request.into_inner();
+        let key: Key = parse_hash("key", request.key)?;
+        let value = self
+            .node
+            .lock()
+            .unwrap()
+            .get_value_at(&key, request.height)
+            .map_err(internal_error)?;
+        let value = bincode::serialize(&value).map_err(internal_error)?;
+        Ok(Response::new(ResolveNameAtResponse { value }))
+    }
+
     async fn submit_transaction(
         &self,
         request: Request<SubmitTransactionRequest>,
     ) -> Result<Response<SubmitTransactionResponse>, Status> {
         let transaction = request.into_inner().transaction;
-        let transaction: AuthorizedTransaction = bincode::deserialize(&transaction).unwrap();
-        let (valid, fee) = self
-            .node
-            .lock()
-            .unwrap()
-            .submit_transaction(transaction)
-            .unwrap();
-        Ok(Response::new(SubmitTransactionResponse { valid, fee }))
+        let transaction: AuthorizedTransaction = bincode::deserialize(&transaction)
+            .map_err(|err| Status::invalid_argument(format!("invalid transaction: {err}")))?;
+        let result = self.node.lock().unwrap().submit_transaction(transaction);
+        let response = match result {
+            Ok(fee) => SubmitTransactionResponse {
+                valid: true,
+                fee,
+                error: String::new(),
+            },
+            Err(err) => SubmitTransactionResponse {
+                valid: false,
+                fee: 0,
+                error: format!("{err:#}"),
+            },
+        };
+        Ok(Response::new(response))
     }
 
     // TODO: Reconsider this RPC.
@@ -88,10 +310,24 @@ impl BitNames for BitNamesNode {
     }
 }
 
+/// Parse a 32 byte hash sent by a client.
+fn parse_hash<T: From<[u8; 32]>>(field: &str, bytes: Vec<u8>) -> Result<T, Status> {
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| Status::invalid_argument(format!("{field} must be 32 bytes")))?;
+    Ok(T::from(bytes))
+}
+
+/// Errors of the node are not caused by the request.
+fn internal_error(err: impl std::fmt::Display) -> Status {
+    Status::internal(format!("{err:#}"))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
     let addr = "[::1]:50051".parse().unwrap();
-    let node = BitNamesNode::new().await?;
+    let node = BitNamesNode::new(&cli).await?;
     println!("BitNames server is listening on {}", addr);
     Server::builder()
         .add_service(BitNamesServer::new(node))
@@ -105,7 +341,8 @@ fn new_env() -> heed::Env {
     //let _ = std::fs::remove_dir_all(&env_path);
     std::fs::create_dir_all(&env_path).unwrap();
     let env = heed::EnvOpenOptions::new()
-        .map_size(10 * 1024 * 1024) // 10MB
+        // Bodies of every connected block are stored.
+        .map_size(1024 * 1024 * 1024 * 1024) // 1TB
         .max_dbs(BitNamesState::NUM_DBS)
         .open(env_path)
         .unwrap();
@@ -4,9 +4,9 @@ use jsonrpsee::proc_macros::rpc;
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct WithdrawalStatus {
-    hash: bitcoin::Txid,
-    nblocksleft: usize,
-    nworkscore: usize,
+    pub hash: bitcoin::Txid,
+    pub nblocksleft: usize,
+    pub nworkscore: usize,
 }
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
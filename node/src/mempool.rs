@@ -0,0 +1,450 @@
+use bitnames_state::*;
+use std::collections::{HashMap, HashSet};
+
+struct MempoolEntry {
+    transaction: AuthorizedTransaction,
+    fee: u64,
+    /// Serialized size in bytes.
+    size: usize,
+}
+
+/// Unconfirmed transactions, no two of them spend the same outpoint, register
+/// or bid on the same name or create the same commitment.
+#[derive(Default)]
+pub struct Mempool {
+    entries: HashMap<Txid, MempoolEntry>,
+    spent_outpoints: HashMap<OutPoint, Txid>,
+    keys: HashMap<Key, Txid>,
+    commitments: HashMap<Commitment, Txid>,
+    // Outputs of mempool transactions, they can be spent by other mempool
+    // transactions.
+    outputs: HashMap<OutPoint, Output>,
+}
+
+impl Mempool {
+    pub fn insert(
+        &mut self,
+        transaction: AuthorizedTransaction,
+        fee: u64,
+    ) -> Result<(), MempoolError> {
+        let txid = transaction.transaction.txid();
+        if self.entries.contains_key(&txid) {
+            return Err(MempoolError::AlreadyInMempool { txid });
+        }
+        let size = bincode::serialized_size(&transaction)? as usize;
+        let (keys, commitments) = transaction_claims(&transaction.transaction);
+        for outpoint in &transaction.transaction.inputs {
+            if let Some(other) = self.spent_outpoints.get(outpoint) {
+                return Err(MempoolError::OutPointAlreadySpent {
+                    outpoint: *outpoint,
+                    txid: *other,
+                });
+            }
+        }
+        for key in &keys {
+            if let Some(other) = self.keys.get(key) {
+                return Err(MempoolError::NameAlreadyRegistered {
+                    key: *key,
+                    txid: *other,
+                });
+            }
+        }
+        for commitment in &commitments {
+            if let Some(other) = self.commitments.get(commitment) {
+                return Err(MempoolError::CommitmentAlreadyCreated {
+                    commitment: *commitment,
+                    txid: *other,
+                });
+            }
+        }
+        for outpoint in &transaction.transaction.inputs {
+            self.spent_outpoints.insert(*outpoint, txid);
+        }
+        for key in keys {
+            self.keys.insert(key, txid);
+        }
+        for commitment in commitments {
+            self.commitments.insert(commitment, txid);
+        }
+        for (vout, output) in transaction.transaction.outputs.iter().enumerate() {
+            let outpoint = OutPoint::Regular {
+                txid,
+                vout: vout as u32,
+            };
+            self.outputs.insert(outpoint, output.clone());
+        }
+        self.entries.insert(
+            txid,
+            MempoolEntry {
+                transaction,
+                fee,
+                size,
+            },
+        );
+        Ok(())
+    }
+
+    pub fn remove(&mut self, txid: &Txid) -> Option<AuthorizedTransaction> {
+        let transaction = self.entries.remove(txid)?.transaction;
+        let (keys, commitments) = transaction_claims(&transaction.transaction);
+        for outpoint in &transaction.transaction.inputs {
+            self.spent_outpoints.remove(outpoint);
+        }
+        for key in &keys {
+            self.keys.remove(key);
+        }
+        for commitment in &commitments {
+            self.commitments.remove(commitment);
+        }
+        for vout in 0..transaction.transaction.outputs.len() {
+            self.outputs.remove(&OutPoint::Regular {
+                txid: *txid,
+                vout: vout as u32,
+            });
+        }
+        Some(transaction)
+    }
+
+    pub fn transactions(&self) -> impl Iterator<Item = &AuthorizedTransaction> {
+        self.entries.values().map(|entry| &entry.transaction)
+    }
+
+    pub fn outputs(&self) -> &HashMap<OutPoint, Output> {
+        &self.outputs
+    }
+
+    /// Drop transactions that are no longer valid, because a conflicting
+    /// transaction was confirmed or they expired.
+    pub fn evict_invalid(&mut self, state: &BitNamesState, rtxn: &heed::RoTxn) {
+        // Evicting a transaction invalidates transactions spending its
+        // outputs, repeat until nothing is evicted.
+        loop {
+            let invalid: Vec<Txid> = self
+                .transactions()
+                .filter(|transaction| {
+                    state
+                        .validate_unconfirmed_transaction(
+                            rtxn,
+                            &self.outputs,
+                            &transaction.transaction,
+                        )
+                        .is_err()
+                })
+                .map(|transaction| transaction.transaction.txid())
+                .collect();
+            if invalid.is_empty() {
+                break;
+            }
+            for txid in &invalid {
+                self.remove(txid);
+            }
+        }
+    }
+
+    /// Body of a new block with the transactions picked by
+    /// `select_transactions`, their fees are paid to `payout_address` and
+    /// burned without one.
+    pub fn block_body(&self, payout_address: Option<Address>) -> Result<Body, MempoolError> {
+        let coinbase = |fee: u64| match payout_address {
+            Some(address) if fee > 0 => vec![Output {
+                address,
+                content: Content::Value(fee),
+            }],
+            _ => vec![],
+        };
+        // Reserve space for the coinbase, its size doesn't depend on the fee.
+        let empty_body_size = bincode::serialized_size(&Body::new(vec![], coinbase(1)))? as usize;
+        let (transactions, fee) = self.select_transactions(BLOCK_SIZE_LIMIT - empty_body_size);
+        Ok(Body::new(transactions, coinbase(fee)))
+    }
+
+    /// Pick transactions with the highest fee per byte that fit in
+    /// `max_size` bytes, returns them with their total fee.
+    ///
+    /// Transactions come after the mempool transactions they spend, a
+    /// transaction is only picked once all of them are.
+    pub fn select_transactions(&self, max_size: usize) -> (Vec<AuthorizedTransaction>, u64) {
+        let mut entries: Vec<(&Txid, &MempoolEntry)> = self.entries.iter().collect();
+        entries.sort_by(|(_, a), (_, b)| {
+            let a_rate = a.fee as u128 * b.size as u128;
+            let b_rate = b.fee as u128 * a.size as u128;
+            b_rate.cmp(&a_rate)
+        });
+        let mut selected = HashSet::<Txid>::new();
+        let mut transactions = vec![];
+        let mut size = 0;
+        let mut fee = 0;
+        // Every pass picks transactions whose parents were picked in earlier
+        // passes.
+        loop {
+            let mut picked = false;
+            for (txid, entry) in &entries {
+                if selected.contains(*txid) || size + entry.size > max_size {
+                    continue;
+                }
+                let inputs = &entry.transaction.transaction.inputs;
+                let parents_selected = inputs.iter().all(|outpoint| match outpoint {
+                    OutPoint::Regular { txid: parent, .. } if self.entries.contains_key(parent) => {
+                        selected.contains(parent)
+                    }
+                    _ => true,
+                });
+                if !parents_selected {
+                    continue;
+                }
+                selected.insert(**txid);
+                transactions.push(entry.transaction.clone());
+                size += entry.size;
+                fee += entry.fee;
+                picked = true;
+            }
+            if !picked {
+                break;
+            }
+        }
+        (transactions, fee)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MempoolError {
+    #[error("transaction {txid:?} is already in the mempool")]
+    AlreadyInMempool { txid: Txid },
+    #[error("{outpoint} is already spent by mempool transaction {txid:?}")]
+    OutPointAlreadySpent { outpoint: OutPoint, txid: Txid },
+    #[error("name {key} is already registered or bid on by mempool transaction {txid:?}")]
+    NameAlreadyRegistered { key: Key, txid: Txid },
+    #[error("commitment {commitment} is already created by mempool transaction {txid:?}")]
+    CommitmentAlreadyCreated { commitment: Commitment, txid: Txid },
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitnames_state::bitcoin::hashes::Hash as _;
+    use bitnames_state::test_utils::*;
+
+    /// Outpoint that only exists in the mempool tests that don't validate.
+    fn fake_outpoint(index: u8) -> OutPoint {
+        OutPoint::Deposit(bitcoin::OutPoint {
+            txid: bitcoin::Txid::from_inner([index; 32]),
+            vout: 0,
+        })
+    }
+
+    /// Validate `transaction` against `state` and the mempool, then add it.
+    fn submit(
+        mempool: &mut Mempool,
+        env: &heed::Env,
+        state: &BitNamesState,
+        transaction: &AuthorizedTransaction,
+    ) {
+        let rtxn = env.read_txn().unwrap();
+        let fee = state
+            .validate_unconfirmed_transaction(&rtxn, mempool.outputs(), &transaction.transaction)
+            .unwrap();
+        mempool.insert(transaction.clone(), fee).unwrap();
+    }
+
+    fn payment(index: u8, funds: OutPoint, value: u64) -> AuthorizedTransaction {
+        let payment = TransactionBuilder::default()
+            .spend(funds)
+            .value(address(index), value)
+            .build();
+        authorize(index, payment)
+    }
+
+    #[test]
+    fn conflicting_spends_are_rejected() {
+        let mut mempool = Mempool::default();
+        let funds = fake_outpoint(0);
+        let first = payment(0, funds, 1000);
+        let second = payment(0, funds, 900);
+        mempool.insert(first.clone(), 0).unwrap();
+        assert!(matches!(
+            mempool.insert(second.clone(), 100),
+            Err(MempoolError::OutPointAlreadySpent { outpoint, txid })
+                if outpoint == funds && txid == first.transaction.txid()
+        ));
+        assert!(matches!(
+            mempool.insert(first.clone(), 0),
+            Err(MempoolError::AlreadyInMempool { .. })
+        ));
+
+        // Removing a transaction releases what it spent.
+        mempool.remove(&first.transaction.txid());
+        mempool.insert(second, 100).unwrap();
+    }
+
+    #[test]
+    fn names_are_claimed_once() {
+        let mut mempool = Mempool::default();
+        let key: Key = hash(&"alice").into();
+        let reveal = TransactionBuilder::default()
+            .spend(fake_outpoint(0))
+            .reveal(address(0), key, Salt::from(hash(&0)))
+            .build();
+        let reveal = authorize(0, reveal);
+        let other_reveal = TransactionBuilder::default()
+            .spend(fake_outpoint(1))
+            .reveal(address(1), key, Salt::from(hash(&1)))
+            .build();
+        let bid = TransactionBuilder::default()
+            .spend(fake_outpoint(2))
+            .bid(address(2), key, hash(&"bid"), 10_000)
+            .build();
+        mempool.insert(reveal.clone(), 0).unwrap();
+        for transaction in [other_reveal, bid.clone()] {
+            assert!(matches!(
+                mempool.insert(authorize(1, transaction), 0),
+                Err(MempoolError::NameAlreadyRegistered { key: claimed, txid })
+                    if claimed == key && txid == reveal.transaction.txid()
+            ));
+        }
+
+        // Bids claim the name as well.
+        mempool.remove(&reveal.transaction.txid());
+        mempool.insert(authorize(2, bid.clone()), 0).unwrap();
+        let other_bid = TransactionBuilder::default()
+            .spend(fake_outpoint(3))
+            .bid(address(3), key, hash(&"other bid"), 10_000)
+            .build();
+        assert!(matches!(
+            mempool.insert(authorize(3, other_bid), 0),
+            Err(MempoolError::NameAlreadyRegistered { txid, .. }) if txid == bid.txid()
+        ));
+    }
+
+    #[test]
+    fn commitments_are_created_once() {
+        let mut mempool = Mempool::default();
+        let key: Key = hash(&"alice").into();
+        let salt = Salt::from(hash(&"salt"));
+        let commit = |index: u8| {
+            let commit = TransactionBuilder::default()
+                .spend(fake_outpoint(index))
+                .commit(address(index), key, salt)
+                .build();
+            authorize(index, commit)
+        };
+        let first = commit(0);
+        mempool.insert(first.clone(), 0).unwrap();
+        assert!(matches!(
+            mempool.insert(commit(1), 0),
+            Err(MempoolError::CommitmentAlreadyCreated { txid, .. })
+                if txid == first.transaction.txid()
+        ));
+    }
+
+    fn txids(transactions: &[AuthorizedTransaction]) -> Vec<Txid> {
+        transactions
+            .iter()
+            .map(|transaction| transaction.transaction.txid())
+            .collect()
+    }
+
+    #[test]
+    fn transactions_are_selected_by_fee_rate() {
+        let mut mempool = Mempool::default();
+        let low = payment(0, fake_outpoint(0), 1000);
+        let high = payment(1, fake_outpoint(1), 1000);
+        mempool.insert(low.clone(), 10).unwrap();
+        mempool.insert(high.clone(), 1000).unwrap();
+        let (transactions, fee) = mempool.select_transactions(BLOCK_SIZE_LIMIT);
+        assert_eq!(txids(&transactions), txids(&[high.clone(), low]));
+        assert_eq!(fee, 1010);
+
+        // Transactions that don't fit are left out.
+        let size = bincode::serialized_size(&high).unwrap() as usize;
+        let (transactions, fee) = mempool.select_transactions(size);
+        assert_eq!(txids(&transactions), txids(&[high]));
+        assert_eq!(fee, 1000);
+    }
+
+    #[test]
+    fn parents_are_selected_before_children() {
+        let mut mempool = Mempool::default();
+        let parent = payment(0, fake_outpoint(0), 1000);
+        let child = payment(0, outpoint(&parent, 0), 900);
+        mempool.insert(parent.clone(), 1).unwrap();
+        // The child pays a higher fee rate, but can't come first.
+        mempool.insert(child.clone(), 100).unwrap();
+        let (transactions, fee) = mempool.select_transactions(BLOCK_SIZE_LIMIT);
+        assert_eq!(
+            txids(&transactions),
+            txids(&[parent.clone(), child.clone()])
+        );
+        assert_eq!(fee, 101);
+
+        // With room for a single transaction, the parent is picked.
+        let size = bincode::serialized_size(&child).unwrap() as usize;
+        let (transactions, _) = mempool.select_transactions(size);
+        assert_eq!(txids(&transactions), txids(&[parent]));
+    }
+
+    #[test]
+    fn transactions_conflicting_with_confirmed_ones_are_evicted() {
+        let env = new_env("eviction");
+        let state = BitNamesState::new(&env).unwrap();
+        let funds = deposit(&env, &state, address(0), 1000);
+        let other_funds = deposit(&env, &state, address(1), 1000);
+        let mut mempool = Mempool::default();
+        let parent = payment(0, funds, 1000);
+        submit(&mut mempool, &env, &state, &parent);
+        let child = payment(0, outpoint(&parent, 0), 1000);
+        submit(&mut mempool, &env, &state, &child);
+        let unrelated = payment(1, other_funds, 1000);
+        submit(&mut mempool, &env, &state, &unrelated);
+
+        // A transaction spending the same funds is confirmed, so the parent
+        // and the child spending its output are evicted.
+        let conflict = payment(0, funds, 500);
+        connect(&env, &state, vec![conflict]).unwrap();
+        let rtxn = env.read_txn().unwrap();
+        mempool.evict_invalid(&state, &rtxn);
+        let txids: Vec<Txid> = mempool
+            .transactions()
+            .map(|transaction| transaction.transaction.txid())
+            .collect();
+        assert_eq!(txids, vec![unrelated.transaction.txid()]);
+        assert!(mempool.outputs().get(&outpoint(&parent, 0)).is_none());
+    }
+
+    #[test]
+    fn block_bodies_are_valid() {
+        let env = new_env("block-body");
+        let state = BitNamesState::new(&env).unwrap();
+        let funds = deposit(&env, &state, address(0), 1000);
+        let other_funds = deposit(&env, &state, address(1), 1000);
+        let mut mempool = Mempool::default();
+        let parent = payment(0, funds, 900);
+        submit(&mut mempool, &env, &state, &parent);
+        let child = payment(0, outpoint(&parent, 0), 800);
+        submit(&mut mempool, &env, &state, &child);
+        let unrelated = payment(1, other_funds, 1000);
+        submit(&mut mempool, &env, &state, &unrelated);
+
+        let body = mempool.block_body(Some(address(9))).unwrap();
+        assert_eq!(body.transactions.len(), 3);
+        let position = |transaction: &AuthorizedTransaction| {
+            let txid = transaction.transaction.txid();
+            body.transactions
+                .iter()
+                .position(|transaction| transaction.txid() == txid)
+                .unwrap()
+        };
+        assert!(position(&parent) < position(&child));
+        let rtxn = env.read_txn().unwrap();
+        validate_body_size(&body).unwrap();
+        state.validate_body(&rtxn, &body).unwrap();
+        assert!(matches!(
+            body.coinbase[..],
+            [Output {
+                content: Content::Value(200),
+                ..
+            }]
+        ));
+    }
+}
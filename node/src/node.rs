@@ -1,4 +1,5 @@
 use crate::drivechain::Drivechain;
+use crate::mempool::Mempool;
 use anyhow::Result;
 use bitnames_api::{
     bit_names_server::{BitNames, BitNamesServer},
@@ -7,25 +8,46 @@ use bitnames_api::{
 use bitnames_state::*;
 use core::str::FromStr;
 use futures::executor::block_on;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 
 pub struct Node {
     env: heed::Env,
     state: BitNamesState,
-    mempool: HashMap<Txid, AuthorizedTransaction>,
+    mempool: Mempool,
     drivechain: Drivechain,
+    // Fees of generated blocks are paid to this address, they are burned
+    // without one.
+    payout_address: Option<Address>,
+    // Mainchain voting progress of the pending bundle, updated whenever two
+    // way peg data is fetched. It isn't a part of the state because it comes
+    // from mainchain.
+    pending_bundle_score: Option<WithdrawalBundleScore>,
 }
 
 impl Node {
-    pub fn new() -> Result<Self> {
+    pub fn new(
+        snapshot: Option<&std::path::Path>,
+        txindex: bool,
+        payout_address: Option<Address>,
+    ) -> Result<Self> {
         let env = new_env();
         let drivechain = Drivechain::new()?;
-        let state = BitNamesState::new(&env)?;
+        let mut state = BitNamesState::new(&env)?;
+        state.index_transactions = txindex;
+        if let Some(snapshot) = snapshot {
+            let file = std::io::BufReader::new(std::fs::File::open(snapshot)?);
+            let mut wtxn = env.write_txn()?;
+            let (height, block_hash) = state.import_snapshot(&mut wtxn, file)?;
+            wtxn.commit()?;
+            println!("imported snapshot at height {height} block {block_hash:?}");
+        }
         Ok(Self {
             env,
             state,
-            mempool: HashMap::new(),
+            mempool: Mempool::default(),
             drivechain,
+            payout_address,
+            pending_bundle_score: None,
         })
     }
 
@@ -36,37 +58,142 @@ impl Node {
         Ok(utxos)
     }
 
-    pub fn submit_transaction(
-        &mut self,
-        transaction: AuthorizedTransaction,
-    ) -> Result<(bool, u64)> {
+    pub fn get_records(&self, key: &Key) -> Result<Vec<Record>> {
         let rtxn = self.env.read_txn().unwrap();
-        // FIXME: check signatures here
-        let result = self
-            .state
-            .validate_transaction(&rtxn, &transaction.transaction);
-        let (valid, fee) = match result {
-            Ok(fee) => (true, fee),
-            Err(_) => (false, 0),
+        let records = self.state.get_records(&rtxn, key)?.unwrap_or_default();
+        Ok(records)
+    }
+
+    pub fn get_name_proof(&self, key: &Key) -> Result<NameProof> {
+        let rtxn = self.env.read_txn().unwrap();
+        let proof = self.state.get_name_proof(&rtxn, key)?;
+        Ok(proof)
+    }
+
+    pub fn get_withdrawal_bundles(&self) -> Result<Vec<(bitcoin::Txid, WithdrawalBundleInfo)>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let bundles = self.state.get_withdrawal_bundles(&rtxn)?;
+        Ok(bundles)
+    }
+
+    pub fn get_withdrawal_status(&self, outpoint: &OutPoint) -> Result<Option<WithdrawalStatus>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let status = self.state.get_withdrawal_status(&rtxn, outpoint)?;
+        Ok(status)
+    }
+
+    /// The pending bundle and its voting progress on mainchain, as of the
+    /// last connected block.
+    pub fn get_pending_bundle(
+        &self,
+    ) -> Result<Option<(WithdrawalBundle, Option<WithdrawalBundleScore>)>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let bundle = match self.state.get_pending_withdrawal_bundle(&rtxn)? {
+            Some(bundle) => bundle,
+            None => return Ok(None),
         };
-        if valid {
-            self.mempool
-                .insert(transaction.transaction.txid(), transaction);
-        }
-        Ok((valid, fee))
+        let txid = bundle.transaction.txid();
+        let score = self.pending_bundle_score.filter(|score| score.txid == txid);
+        Ok(Some((bundle, score)))
+    }
+
+    pub fn get_header(&self, height: u32) -> Result<Option<Header>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let header = self.state.get_header(&rtxn, height)?;
+        Ok(header)
+    }
+
+    /// Block at `height`, `None` if its body isn't stored.
+    pub fn get_block(&self, height: u32) -> Result<Option<(Header, Body)>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let header = self.state.get_header(&rtxn, height)?;
+        let body = self.state.get_body(&rtxn, height)?;
+        Ok(header.zip(body))
+    }
+
+    pub fn get_block_by_hash(&self, block_hash: &BlockHash) -> Result<Option<(u32, Header, Body)>> {
+        let height = {
+            let rtxn = self.env.read_txn().unwrap();
+            match self.state.get_block_height(&rtxn, block_hash)? {
+                Some(height) => height,
+                None => return Ok(None),
+            }
+        };
+        let block = self
+            .get_block(height)?
+            .map(|(header, body)| (height, header, body));
+        Ok(block)
+    }
+
+    pub fn get_transaction(&self, txid: &Txid) -> Result<Option<ConfirmedTransaction>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let transaction = self.state.get_transaction(&rtxn, txid)?;
+        Ok(transaction)
+    }
+
+    /// Best block height and hash, and the mainchain block it was mined on
+    /// top of.
+    pub fn get_chain_info(&self) -> Result<(u32, BlockHash, bitcoin::BlockHash)> {
+        let rtxn = self.env.read_txn().unwrap();
+        let (height, header) = self.state.get_best_header(&rtxn)?;
+        Ok((height, header.block_hash(), header.prev_main_block_hash))
+    }
+
+    pub fn get_value_at(&self, key: &Key, height: u32) -> Result<Option<Value>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let value = self.state.get_value_at(&rtxn, key, height)?;
+        Ok(value)
+    }
+
+    pub fn get_key_history(
+        &self,
+        key: &Key,
+        start_height: u32,
+        limit: usize,
+    ) -> Result<Vec<KeyHistoryEntry>> {
+        let rtxn = self.env.read_txn().unwrap();
+        let history = self
+            .state
+            .get_key_history(&rtxn, key, start_height, limit)?;
+        Ok(history)
+    }
+
+    /// Add a transaction to the mempool, returns its fee.
+    pub fn submit_transaction(&mut self, transaction: AuthorizedTransaction) -> Result<u64> {
+        let rtxn = self.env.read_txn().unwrap();
+        // FIXME: check signatures here
+        let fee = self.state.validate_unconfirmed_transaction(
+            &rtxn,
+            self.mempool.outputs(),
+            &transaction.transaction,
+        )?;
+        self.mempool.insert(transaction, fee)?;
+        Ok(fee)
+    }
+
+    /// Drop mempool transactions that are no longer valid, because a
+    /// conflicting transaction was confirmed or they expired.
+    fn evict_invalid_transactions(&mut self) {
+        let rtxn = self.env.read_txn().unwrap();
+        self.mempool.evict_invalid(&self.state, &rtxn);
     }
 
     pub fn generate_block(&self) -> Result<(Header, Body)> {
-        let transactions = self.mempool.values().cloned().collect();
-        let body = Body::new(transactions, vec![]);
+        let body = self.mempool.block_body(self.payout_address)?;
         let rtxn = self.env.read_txn().unwrap();
+        // Mempool transactions are valid and don't conflict, so the body
+        // should be valid as well.
+        validate_body_size(&body)?;
+        self.state.validate_body(&rtxn, &body)?;
         let (_, prev_header) = self.state.get_best_header(&rtxn).unwrap();
+        drop(rtxn);
         let prev_side_block_hash = prev_header.block_hash();
         let prev_main_block_hash = block_on(self.drivechain.get_mainchain_tip()).unwrap();
         let header = Header {
             merkle_root: body.compute_merkle_root(),
             prev_side_block_hash,
             prev_main_block_hash,
+            state_root: self.state.compute_state_root(&self.env, &body)?,
         };
         Ok((header, body))
     }
@@ -79,18 +206,55 @@ impl Node {
 
     pub fn connect_block(&mut self, header: &Header, body: &Body) -> Result<()> {
         let mut wtxn = self.env.write_txn().unwrap();
-        let start = self.state.get_last_deposit_block_hash(&wtxn).unwrap();
+        // Blocks after the parent of `header` were orphaned, roll them back
+        // and return their transactions to the mempool.
+        let parent_height = self
+            .state
+            .get_block_height(&wtxn, &header.prev_side_block_hash)?
+            .ok_or_else(|| {
+                anyhow::anyhow!("unknown parent block {:?}", header.prev_side_block_hash)
+            })?;
+        let mut orphaned = vec![];
+        loop {
+            let (height, _) = self.state.get_best_header(&wtxn)?;
+            if height == parent_height {
+                break;
+            }
+            if let Some(body) = self.state.get_body(&wtxn, height)? {
+                orphaned.push(body);
+            }
+            self.state.disconnect_block(&mut wtxn)?;
+        }
+        let start = self.state.get_last_deposit_block_hash(&wtxn)?;
         let end = header.prev_main_block_hash;
-        let two_way_peg_data = block_on(self.drivechain.get_two_way_peg_data(end, start)).unwrap();
-        dbg!(&header, &two_way_peg_data, &body);
+        let pending_txid = self
+            .state
+            .get_pending_withdrawal_bundle(&wtxn)?
+            .map(|bundle| bundle.transaction.txid());
+        let two_way_peg_data = block_on(self.drivechain.get_two_way_peg_data(
+            end,
+            start,
+            pending_txid,
+        ))?;
+        // Voting progress is unknown if mainchain can't report it, the block
+        // is connected regardless.
+        let score = match pending_txid {
+            Some(txid) => block_on(self.drivechain.get_withdrawal_bundle_score(txid))
+                .unwrap_or_else(|err| {
+                    log::warn!("failed to get score of bundle {txid}: {err:#}");
+                    None
+                }),
+            None => None,
+        };
+        self.state
+            .validate_block(&wtxn, header, body, &two_way_peg_data)?;
         let txids = body
             .transactions
             .iter()
             .map(Transaction::txid)
             .collect::<Vec<_>>();
         self.state
-            .connect_block(&mut wtxn, &header, &body, &two_way_peg_data)
-            .unwrap();
+            .connect_block(&mut wtxn, header, body, &two_way_peg_data)?;
         if let Some(bundle) = self.state.get_pending_withdrawal_bundle(&wtxn).unwrap() {
             block_on(
                 self.drivechain
@@ -102,6 +266,18 @@ impl Node {
             self.mempool.remove(txid);
         }
         wtxn.commit().unwrap();
+        // Ignored by `get_pending_bundle` once the bundle is no longer pending.
+        self.pending_bundle_score = score;
+        // Oldest blocks first, so that parents are added before children.
+        for body in orphaned.iter().rev() {
+            for transaction in authorized_transactions(body) {
+                let txid = transaction.transaction.txid();
+                if let Err(err) = self.submit_transaction(transaction) {
+                    log::debug!("dropped orphaned transaction {txid:?}: {err:#}");
+                }
+            }
+        }
+        self.evict_invalid_transactions();
         Ok(())
     }
 
@@ -116,12 +292,29 @@ impl Node {
     }
 }
 
+/// Transactions of `body` with their authorizations.
+fn authorized_transactions(body: &Body) -> Vec<AuthorizedTransaction> {
+    let mut authorizations = body.authorizations.iter();
+    body.transactions
+        .iter()
+        .map(|transaction| AuthorizedTransaction {
+            transaction: transaction.clone(),
+            authorizations: authorizations
+                .by_ref()
+                .take(transaction.inputs.len())
+                .cloned()
+                .collect(),
+        })
+        .collect()
+}
+
 fn new_env() -> heed::Env {
     let env_path = std::path::Path::new("target").join("clear-database.mdb");
     //let _ = std::fs::remove_dir_all(&env_path);
     std::fs::create_dir_all(&env_path).unwrap();
     let env = heed::EnvOpenOptions::new()
-        .map_size(10 * 1024 * 1024) // 10MB
+        // Bodies of every connected block are stored.
+        .map_size(1024 * 1024 * 1024 * 1024) // 1TB
         .max_dbs(BitNamesState::NUM_DBS)
         .open(env_path)
         .unwrap();
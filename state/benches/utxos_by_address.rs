@@ -0,0 +1,108 @@
+//! Compares looking up the utxos of an address through `address_to_outpoints`
+//! with scanning the whole utxo set, which is what `get_utxos_by_addresses`
+//! did before the index was added.
+use bitnames_state::bitcoin::hashes::Hash as _;
+use bitnames_state::*;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::HashSet;
+
+const ADDRESSES: u32 = 1000;
+
+fn new_state(utxos: u32) -> (heed::Env, BitNamesState) {
+    let env_path = std::env::temp_dir().join(format!(
+        "bitnames-bench-{}-{}.mdb",
+        utxos,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&env_path);
+    std::fs::create_dir_all(&env_path).unwrap();
+    let env = heed::EnvOpenOptions::new()
+        .map_size(1024 * 1024 * 1024) // 1GB
+        .max_dbs(BitNamesState::NUM_DBS)
+        .open(env_path)
+        .unwrap();
+    let state = BitNamesState::new(&env).unwrap();
+    let mut two_way_peg_data = TwoWayPegData {
+        deposit_block: Some((bitcoin::BlockHash::from_inner([1; 32]), 1)),
+        ..Default::default()
+    };
+    for index in 0..utxos {
+        let outpoint = OutPoint::Deposit(bitcoin::OutPoint {
+            txid: bitcoin::Txid::from_inner(hash(&index)),
+            vout: 0,
+        });
+        let output = Output {
+            address: bench_address(index % ADDRESSES),
+            content: Content::Value(1000),
+        };
+        two_way_peg_data.deposits.insert(outpoint, output);
+    }
+    let mut wtxn = env.write_txn().unwrap();
+    let (_, genesis) = state.get_best_header(&wtxn).unwrap();
+    let body = Body::new(vec![], vec![]);
+    let header = Header {
+        prev_side_block_hash: genesis.block_hash(),
+        prev_main_block_hash: genesis.prev_main_block_hash,
+        merkle_root: body.compute_merkle_root(),
+        state_root: state.get_state_root(&wtxn).unwrap(),
+    };
+    state
+        .connect_block(&mut wtxn, &header, &body, &two_way_peg_data)
+        .unwrap();
+    wtxn.commit().unwrap();
+    (env, state)
+}
+
+fn bench_address(index: u32) -> Address {
+    Address::from(hash(&index))
+}
+
+fn scan_utxos(
+    state: &BitNamesState,
+    rtxn: &heed::RoTxn,
+    addresses: &HashSet<Address>,
+) -> Vec<(OutPoint, Output)> {
+    state
+        .utxos
+        .iter(rtxn)
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|(_, output)| addresses.contains(&output.address))
+        .collect()
+}
+
+fn utxos_by_address(c: &mut Criterion) {
+    let mut group = c.benchmark_group("utxos_by_address");
+    for utxos in [10_000, 100_000] {
+        let (env, state) = new_state(utxos);
+        let rtxn = env.read_txn().unwrap();
+        let addresses = HashSet::from([bench_address(7)]);
+        assert_eq!(
+            scan_utxos(&state, &rtxn, &addresses).len(),
+            state
+                .get_utxos_by_addresses(&rtxn, &addresses)
+                .unwrap()
+                .len()
+        );
+        group.bench_with_input(
+            BenchmarkId::new("scan", utxos),
+            &addresses,
+            |b, addresses| b.iter(|| scan_utxos(&state, &rtxn, black_box(addresses))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("index", utxos),
+            &addresses,
+            |b, addresses| {
+                b.iter(|| {
+                    state
+                        .get_utxos_by_addresses(&rtxn, black_box(addresses))
+                        .unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, utxos_by_address);
+criterion_main!(benches);
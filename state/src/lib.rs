@@ -5,15 +5,36 @@ use std::collections::{HashMap, HashSet};
 pub use bitnames_types::*;
 use heed::types::*;
 use heed::{Database, RoTxn, RwTxn};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 
 #[derive(Clone)]
 pub struct BitNamesState {
     pub key_to_value: Database<SerdeBincode<Key>, SerdeBincode<Value>>,
+    // Value of a name with records is the hash of its records.
+    pub key_to_records: Database<SerdeBincode<Key>, SerdeBincode<Vec<Record>>>,
 
     pub commitment_to_height: Database<SerdeBincode<Commitment>, OwnedType<u32>>,
     pub commitment_to_outpoint: Database<SerdeBincode<Commitment>, SerdeBincode<OutPoint>>,
+    pub height_to_commitments: Database<OwnedType<u32>, SerdeBincode<Vec<Commitment>>>,
     pub key_to_commitment: Database<SerdeBincode<Key>, SerdeBincode<Commitment>>,
     pub commitment_to_key: Database<SerdeBincode<Commitment>, SerdeBincode<Key>>,
+    // Heights are stored big endian, so that entries for a key are sorted by
+    // height. The last entry of a block holds the value of the key at the
+    // end of that block.
+    pub key_history: Database<SerdeBincode<(Key, [u8; 4])>, SerdeBincode<Vec<KeyHistoryEntry>>>,
+    pub key_to_parent: Database<SerdeBincode<Key>, SerdeBincode<Key>>,
+    pub key_to_subnames: Database<SerdeBincode<Key>, SerdeBincode<Vec<Key>>>,
+    pub key_to_auction: Database<SerdeBincode<Key>, SerdeBincode<Auction>>,
+    pub auction_end_height_to_keys: Database<OwnedType<u32>, SerdeBincode<Vec<Key>>>,
+    pub key_to_expiry_height: Database<SerdeBincode<Key>, OwnedType<u32>>,
+    // May list keys that were renewed since, check `key_to_expiry_height`.
+    pub expiry_height_to_keys: Database<OwnedType<u32>, SerdeBincode<Vec<Key>>>,
+    // Sparse Merkle tree over `key_to_value`, nodes are keyed by depth and
+    // path prefix. Only nodes of non empty subtrees are stored.
+    pub state_tree: Database<SerdeBincode<(u16, Hash)>, SerdeBincode<Hash>>,
 
     // TODO: Include commitment to spent inputs in withdrawal bundle, without it
     // there is ambiguity
@@ -24,61 +45,412 @@ pub struct BitNamesState {
     // withdrawals deterministic.
     pub last_withdrawal_bundle: Database<OwnedType<u32>, SerdeBincode<WithdrawalBundle>>,
     pub last_withdrawal_bundle_failure_height: Database<OwnedType<u32>, OwnedType<u32>>,
-    pub last_deposit_block: Database<OwnedType<u32>, SerdeBincode<bitcoin::BlockHash>>,
+    // Hash and height of the mainchain block with the last deposit.
+    pub last_deposit_block: Database<OwnedType<u32>, SerdeBincode<(bitcoin::BlockHash, u32)>>,
+    pub withdrawal_bundles:
+        Database<SerdeBincode<bitcoin::Txid>, SerdeBincode<WithdrawalBundleInfo>>,
+    // Last bundle that included a withdrawal, it is included again if that
+    // bundle fails.
+    pub outpoint_to_withdrawal_bundle:
+        Database<SerdeBincode<OutPoint>, SerdeBincode<bitcoin::Txid>>,
+    // Status of every withdrawal output that was created, kept after it is
+    // paid out.
+    pub withdrawal_statuses: Database<SerdeBincode<OutPoint>, SerdeBincode<WithdrawalStatus>>,
 
     pub utxos: Database<SerdeBincode<OutPoint>, SerdeBincode<Output>>,
+    // Keyed by address followed by outpoint, so that outpoints of an address
+    // can be found by prefix.
+    pub address_to_outpoints: Database<SerdeBincode<(Address, OutPoint)>, Unit>,
     // Should headers be a part of the state?
     pub headers: Database<OwnedType<u32>, SerdeBincode<Header>>,
+    // Bodies of blocks connected after the block store was added.
+    pub bodies: Database<OwnedType<u32>, SerdeBincode<Body>>,
+    pub block_hash_to_height: Database<SerdeBincode<BlockHash>, OwnedType<u32>>,
+    // Only kept when `index_transactions` is set, and only for blocks
+    // connected while it was set.
+    pub txid_to_location: Database<SerdeBincode<Txid>, SerdeBincode<TransactionLocation>>,
+    pub disconnect_data: Database<OwnedType<u32>, SerdeBincode<DisconnectData>>,
+    pub metadata: Database<Str, OwnedType<u32>>,
+    pub params: ConsensusParams,
+    /// Populate `txid_to_location` in `connect_block`.
+    pub index_transactions: bool,
 }
 
 impl BitNamesState {
-    pub const NUM_DBS: u32 = 10;
+    pub const NUM_DBS: u32 = 29;
     pub const WITHDRAWAL_BUNDLE_FAILURE_GAP: u32 = 100;
 
     pub fn new(env: &heed::Env) -> Result<Self, Error> {
         let key_to_value = env.create_database(Some("key_to_value"))?;
+        let key_to_records = env.create_database(Some("key_to_records"))?;
         let commitment_to_height = env.create_database(Some("commitment_to_height"))?;
         let commitment_to_outpoint = env.create_database(Some("commitment_to_outpoint"))?;
+        let height_to_commitments = env.create_database(Some("height_to_commitments"))?;
         let key_to_commitment = env.create_database(Some("key_to_commitment"))?;
         let commitment_to_key = env.create_database(Some("commitment_to_key"))?;
+        let key_history = env.create_database(Some("key_history"))?;
+        let key_to_parent = env.create_database(Some("key_to_parent"))?;
+        let key_to_subnames = env.create_database(Some("key_to_subnames"))?;
+        let key_to_auction = env.create_database(Some("key_to_auction"))?;
+        let auction_end_height_to_keys = env.create_database(Some("auction_end_height_to_keys"))?;
+        let key_to_expiry_height = env.create_database(Some("key_to_expiry_height"))?;
+        let expiry_height_to_keys = env.create_database(Some("expiry_height_to_keys"))?;
+        let state_tree = env.create_database(Some("state_tree"))?;
 
         let last_withdrawal_bundle = env.create_database(Some("last_withdrawal_bundle"))?;
 
         let last_withdrawal_bundle_failure_height =
             env.create_database(Some("last_withdrawal_bundle_failure_height"))?;
         let last_deposit_block = env.create_database(Some("last_deposit_block"))?;
+        let withdrawal_bundles = env.create_database(Some("withdrawal_bundles"))?;
+        let outpoint_to_withdrawal_bundle =
+            env.create_database(Some("outpoint_to_withdrawal_bundle"))?;
+        let withdrawal_statuses = env.create_database(Some("withdrawal_statuses"))?;
 
         let utxos = env.create_database(Some("utxos"))?;
+        let address_to_outpoints = env.create_database(Some("address_to_outpoints"))?;
 
         let headers: Database<OwnedType<u32>, SerdeBincode<Header>> =
             env.create_database(Some("headers"))?;
+        let bodies = env.create_database(Some("bodies"))?;
+        let block_hash_to_height = env.create_database(Some("block_hash_to_height"))?;
+        let txid_to_location = env.create_database(Some("txid_to_location"))?;
+        let disconnect_data = env.create_database(Some("disconnect_data"))?;
+        let metadata = env.create_database(Some("metadata"))?;
 
-        {
-            let mut wtxn = env.write_txn()?;
-            if headers.is_empty(&wtxn)? {
-                headers.append(&mut wtxn, &0, &Header::genesis())?;
-                wtxn.commit()?;
-            }
-        }
-
-        Ok(Self {
+        let state = Self {
             key_to_value,
+            key_to_records,
             commitment_to_height,
             commitment_to_outpoint,
+            height_to_commitments,
             key_to_commitment,
             commitment_to_key,
+            key_history,
+            key_to_parent,
+            key_to_subnames,
+            key_to_auction,
+            auction_end_height_to_keys,
+            key_to_expiry_height,
+            expiry_height_to_keys,
+            state_tree,
             last_withdrawal_bundle,
             last_withdrawal_bundle_failure_height,
             last_deposit_block,
+            withdrawal_bundles,
+            outpoint_to_withdrawal_bundle,
+            withdrawal_statuses,
             utxos,
+            address_to_outpoints,
             headers,
-        })
+            bodies,
+            block_hash_to_height,
+            txid_to_location,
+            disconnect_data,
+            metadata,
+            params: ConsensusParams::default(),
+            index_transactions: false,
+        };
+        let mut wtxn = env.write_txn()?;
+        state.migrate(&mut wtxn)?;
+        if state.headers.is_empty(&wtxn)? {
+            let genesis = Header::genesis();
+            state.headers.append(&mut wtxn, &0, &genesis)?;
+            state
+                .block_hash_to_height
+                .put(&mut wtxn, &genesis.block_hash(), &0)?;
+        }
+        wtxn.commit()?;
+        Ok(state)
+    }
+
+    /// Upgrade the databases to `SCHEMA_VERSION`.
+    fn migrate(&self, wtxn: &mut RwTxn) -> Result<(), Error> {
+        let version = match self.metadata.get(wtxn, SCHEMA_VERSION_KEY)? {
+            Some(version) => version,
+            // Databases without a version were either just created, or
+            // created before versioning.
+            None if self.headers.is_empty(wtxn)? => SCHEMA_VERSION,
+            None => 0,
+        };
+        if version > SCHEMA_VERSION {
+            Err(SchemaError::UnsupportedVersion {
+                version,
+                supported_version: SCHEMA_VERSION,
+            })?;
+        }
+        for migration in &MIGRATIONS[version as usize..] {
+            migration(self, wtxn)?;
+        }
+        self.metadata
+            .put(wtxn, SCHEMA_VERSION_KEY, &SCHEMA_VERSION)?;
+        Ok(())
+    }
+
+    /// Migration from version 0, the layout before versioning. Converts
+    /// headers and the last deposit block, and builds every database added
+    /// since from the utxo set.
+    ///
+    /// * Headers commit to a state root, which is zero for headers connected
+    ///   before. Their hashes change, so every header is relinked to the new
+    ///   hash of the previous one.
+    /// * The mainchain height of the last deposit block is unknown and set to
+    ///   0, so the next deposit block can be at any height.
+    /// * Names connected before have no history, they get a single entry for
+    ///   their unspent `KeyValue` or `Reveal` output at the best block.
+    /// * Heights at which names were last updated are unknown, so names
+    ///   expire `name_max_age` blocks after the best block.
+    /// * The pending bundle is added to the bundle history as created at the
+    ///   best block, withdrawals paid out before have no status.
+    /// * Blocks connected before have no undo data or body, so they can't be
+    ///   disconnected.
+    fn migrate_from_baseline(&self, wtxn: &mut RwTxn) -> Result<(), Error> {
+        let headers = self
+            .headers
+            .remap_data_type::<SerdeBincode<v0::Header>>()
+            .iter(wtxn)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut prev_side_block_hash = None;
+        for (height, header) in headers {
+            let header = Header {
+                prev_side_block_hash: prev_side_block_hash.unwrap_or(header.prev_side_block_hash),
+                prev_main_block_hash: header.prev_main_block_hash,
+                merkle_root: header.merkle_root,
+                state_root: Hash::default(),
+            };
+            prev_side_block_hash = Some(header.block_hash());
+            self.headers.put(wtxn, &height, &header)?;
+        }
+        self.index_block_hashes(wtxn)?;
+        let (best_height, _) = self.get_best_header(wtxn)?;
+
+        let last_deposit_block_hash = self
+            .last_deposit_block
+            .remap_data_type::<SerdeBincode<bitcoin::BlockHash>>()
+            .get(wtxn, &0)?;
+        if let Some(block_hash) = last_deposit_block_hash {
+            self.last_deposit_block.put(wtxn, &0, &(block_hash, 0))?;
+        }
+
+        let utxos = self
+            .utxos
+            .iter(wtxn)?
+            .collect::<Result<Vec<(OutPoint, Output)>, _>>()?;
+        for (outpoint, output) in &utxos {
+            self.put_utxo(wtxn, outpoint, output)?;
+        }
+
+        let commitments = self
+            .commitment_to_height
+            .iter(wtxn)?
+            .collect::<Result<Vec<(Commitment, u32)>, _>>()?;
+        for (commitment, height) in commitments {
+            let mut height_commitments = self
+                .height_to_commitments
+                .get(wtxn, &height)?
+                .unwrap_or_default();
+            height_commitments.push(commitment);
+            self.height_to_commitments
+                .put(wtxn, &height, &height_commitments)?;
+        }
+
+        let names = self
+            .key_to_value
+            .iter(wtxn)?
+            .collect::<Result<Vec<(Key, Value)>, _>>()?;
+        // `KeyValue` outputs are created after the `Reveal` output of a name,
+        // so they take precedence.
+        let mut name_outputs: HashMap<Key, (OutPoint, Address)> = HashMap::new();
+        for (outpoint, output) in &utxos {
+            match output.content {
+                Content::Custom(BitNamesOutput::Reveal { key, .. }) => {
+                    name_outputs
+                        .entry(key)
+                        .or_insert((*outpoint, output.address));
+                }
+                Content::Custom(BitNamesOutput::KeyValue { key, .. }) => {
+                    name_outputs.insert(key, (*outpoint, output.address));
+                }
+                _ => {}
+            }
+        }
+        // Databases are built from scratch, there is nothing to undo.
+        let mut undo = DisconnectData::default();
+        for (key, value) in &names {
+            if let Some((outpoint, address)) = name_outputs.get(key) {
+                let entry = KeyHistoryEntry {
+                    height: best_height,
+                    outpoint: *outpoint,
+                    address: *address,
+                    value: Some(*value),
+                };
+                self.push_key_history(wtxn, &mut undo, key, entry)?;
+            }
+            self.renew_key(wtxn, &mut undo, key, best_height)?;
+            self.update_state_tree(wtxn, key)?;
+        }
+
+        for (outpoint, output) in &utxos {
+            if let Content::Withdrawal { .. } = output.content {
+                self.withdrawal_statuses
+                    .put(wtxn, outpoint, &WithdrawalStatus::Pending)?;
+            }
+        }
+        if let Some(bundle) = self.last_withdrawal_bundle.get(wtxn, &0)? {
+            self.put_withdrawal_bundle_info(wtxn, &mut undo, &bundle, best_height)?;
+            let status = WithdrawalStatus::Bundled {
+                txid: bundle.transaction.txid(),
+            };
+            for outpoint in bundle.spent_utxos.keys() {
+                self.withdrawal_statuses.put(wtxn, outpoint, &status)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Index headers by block hash, after migrating from version 0 or
+    /// importing a snapshot.
+    fn index_block_hashes(&self, wtxn: &mut RwTxn) -> Result<(), Error> {
+        let headers = self
+            .headers
+            .iter(wtxn)?
+            .collect::<Result<Vec<(u32, Header)>, _>>()?;
+        self.block_hash_to_height.clear(wtxn)?;
+        for (height, header) in &headers {
+            self.block_hash_to_height
+                .put(wtxn, &header.block_hash(), height)?;
+        }
+        Ok(())
     }
 
     pub fn get_value(&self, rtxn: &RoTxn, key: &Key) -> Result<Option<Value>, Error> {
         Ok(self.key_to_value.get(rtxn, key)?)
     }
 
+    /// Get the value `key` had after block `height` was connected.
+    pub fn get_value_at(
+        &self,
+        rtxn: &RoTxn,
+        key: &Key,
+        height: u32,
+    ) -> Result<Option<Value>, Error> {
+        let entry = self.get_last_key_history_entry(rtxn, key, height)?;
+        Ok(entry.and_then(|entry| entry.value))
+    }
+
+    /// Get the last history entry of `key` at or before `height`.
+    fn get_last_key_history_entry(
+        &self,
+        rtxn: &RoTxn,
+        key: &Key,
+        height: u32,
+    ) -> Result<Option<KeyHistoryEntry>, Error> {
+        let range = (*key, [0; 4])..=(*key, height.to_be_bytes());
+        let entry = self
+            .key_history
+            .rev_range(rtxn, &range)?
+            .next()
+            .transpose()?
+            .and_then(|(_, mut entries)| entries.pop());
+        Ok(entry)
+    }
+
+    /// Get values of `key` set at or after `start_height`, oldest first.
+    ///
+    /// Stops once `limit` entries are collected, but never splits the entries
+    /// of a single block, so the next page starts at the height of the last
+    /// returned entry plus one. A `limit` of 0 returns every entry, it is
+    /// what requests that leave the limit unset ask for.
+    pub fn get_key_history(
+        &self,
+        rtxn: &RoTxn,
+        key: &Key,
+        start_height: u32,
+        limit: usize,
+    ) -> Result<Vec<KeyHistoryEntry>, Error> {
+        let range = (*key, start_height.to_be_bytes())..=(*key, u32::MAX.to_be_bytes());
+        let mut history = vec![];
+        for item in self.key_history.range(rtxn, &range)? {
+            if limit != 0 && history.len() >= limit {
+                break;
+            }
+            let (_, entries) = item?;
+            history.extend(entries);
+        }
+        Ok(history)
+    }
+
+    pub fn get_records(&self, rtxn: &RoTxn, key: &Key) -> Result<Option<Vec<Record>>, Error> {
+        Ok(self.key_to_records.get(rtxn, key)?)
+    }
+
+    pub fn get_parent(&self, rtxn: &RoTxn, key: &Key) -> Result<Option<Key>, Error> {
+        Ok(self.key_to_parent.get(rtxn, key)?)
+    }
+
+    pub fn get_subnames(&self, rtxn: &RoTxn, key: &Key) -> Result<Vec<Key>, Error> {
+        Ok(self.key_to_subnames.get(rtxn, key)?.unwrap_or_default())
+    }
+
+    pub fn get_state_root(&self, rtxn: &RoTxn) -> Result<Hash, Error> {
+        let root = self
+            .state_tree
+            .get(rtxn, &state_tree_position(&[0; 32], 0))?;
+        Ok(root.unwrap_or_default())
+    }
+
+    /// Prove the current value of `key` against the current state root.
+    pub fn get_name_proof(&self, rtxn: &RoTxn, key: &Key) -> Result<NameProof, Error> {
+        let path: &Hash = key.into();
+        let siblings = (0..STATE_TREE_DEPTH)
+            .rev()
+            .map(|depth| {
+                Ok(self
+                    .state_tree
+                    .get(rtxn, &state_tree_sibling(path, depth))?)
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(NameProof {
+            key: *key,
+            value: self.key_to_value.get(rtxn, key)?,
+            siblings,
+        })
+    }
+
+    /// Recompute the path to `key` in the state tree from its current value.
+    fn update_state_tree(&self, wtxn: &mut RwTxn, key: &Key) -> Result<(), Error> {
+        let path: &Hash = key.into();
+        let mut node = match self.key_to_value.get(wtxn, key)? {
+            Some(value) => state_tree_leaf(key, &value),
+            None => Hash::default(),
+        };
+        for depth in (0..=STATE_TREE_DEPTH).rev() {
+            let position = state_tree_position(path, depth);
+            if node == Hash::default() {
+                self.state_tree.delete(wtxn, &position)?;
+            } else {
+                self.state_tree.put(wtxn, &position, &node)?;
+            }
+            if depth == 0 {
+                break;
+            }
+            let sibling = self
+                .state_tree
+                .get(wtxn, &state_tree_sibling(path, depth - 1))?
+                .unwrap_or_default();
+            node = if state_tree_bit(key, depth - 1) {
+                state_tree_node(&sibling, &node)
+            } else {
+                state_tree_node(&node, &sibling)
+            };
+        }
+        Ok(())
+    }
+
+    pub fn get_auction(&self, rtxn: &RoTxn, key: &Key) -> Result<Option<Auction>, Error> {
+        Ok(self.key_to_auction.get(rtxn, key)?)
+    }
+
     pub fn get_utxo(&self, rtxn: &RoTxn, outpoint: &OutPoint) -> Result<Option<Output>, Error> {
         Ok(self.utxos.get(rtxn, outpoint)?)
     }
@@ -98,21 +470,270 @@ impl BitNamesState {
         Ok(spent_utxos)
     }
 
+    /// Like `get_utxos`, but outputs in `unconfirmed` can be spent too.
+    fn get_unconfirmed_utxos(
+        &self,
+        txn: &RoTxn,
+        unconfirmed: &HashMap<OutPoint, Output>,
+        inputs: &[OutPoint],
+    ) -> Result<Vec<Output>, Error> {
+        let mut spent_utxos = vec![];
+        for outpoint in inputs {
+            let output = match unconfirmed.get(outpoint) {
+                Some(output) => output.clone(),
+                None => self.utxos.get(txn, outpoint)?.ok_or::<Error>(
+                    sdk_types::Error::UtxoDoesNotExist {
+                        outpoint: *outpoint,
+                    }
+                    .into(),
+                )?,
+            };
+            spent_utxos.push(output);
+        }
+        Ok(spent_utxos)
+    }
+
     pub fn get_utxos_by_addresses(
         &self,
         txn: &RoTxn,
         addresses: &HashSet<Address>,
     ) -> Result<Vec<(OutPoint, Output)>, Error> {
         let mut utxos = vec![];
-        for item in self.utxos.iter(txn)? {
-            let utxo @ (_outpoint, output) = &item?;
-            if addresses.contains(&output.address) {
-                utxos.push(utxo.clone());
+        for address in addresses {
+            let prefix = bincode::serialize(address)?;
+            let mut outpoints = vec![];
+            for item in self
+                .address_to_outpoints
+                .remap_key_type::<ByteSlice>()
+                .prefix_iter(txn, &prefix)?
+            {
+                let (key, ()) = item?;
+                let (_, outpoint): (Address, OutPoint) = bincode::deserialize(key)?;
+                outpoints.push(outpoint);
+            }
+            for outpoint in outpoints {
+                let output = self
+                    .utxos
+                    .get(txn, &outpoint)?
+                    .ok_or::<Error>(sdk_types::Error::UtxoDoesNotExist { outpoint }.into())?;
+                utxos.push((outpoint, output));
             }
         }
         Ok(utxos)
     }
 
+    /// Add a new pending bundle to the bundle history.
+    fn put_withdrawal_bundle_info(
+        &self,
+        wtxn: &mut RwTxn,
+        undo: &mut DisconnectData,
+        bundle: &WithdrawalBundle,
+        creation_height: u32,
+    ) -> Result<(), Error> {
+        let txid = bundle.transaction.txid();
+        let info = WithdrawalBundleInfo {
+            status: None,
+            creation_height,
+            outpoints: sorted_outpoints(&bundle.spent_utxos),
+        };
+        for outpoint in &info.outpoints {
+            record(
+                &self.outpoint_to_withdrawal_bundle,
+                wtxn,
+                &mut undo.outpoint_to_withdrawal_bundle,
+                outpoint,
+            )?;
+            self.outpoint_to_withdrawal_bundle
+                .put(wtxn, outpoint, &txid)?;
+        }
+        record(
+            &self.withdrawal_bundles,
+            wtxn,
+            &mut undo.withdrawal_bundles,
+            &txid,
+        )?;
+        self.withdrawal_bundles.put(wtxn, &txid, &info)?;
+        Ok(())
+    }
+
+    fn put_withdrawal_status(
+        &self,
+        wtxn: &mut RwTxn,
+        undo: &mut DisconnectData,
+        outpoint: &OutPoint,
+        status: &WithdrawalStatus,
+    ) -> Result<(), Error> {
+        record(
+            &self.withdrawal_statuses,
+            wtxn,
+            &mut undo.withdrawal_statuses,
+            outpoint,
+        )?;
+        self.withdrawal_statuses.put(wtxn, outpoint, status)?;
+        Ok(())
+    }
+
+    /// Push back the expiry of `key` to `name_max_age` blocks after
+    /// `block_height`.
+    fn renew_key(
+        &self,
+        wtxn: &mut RwTxn,
+        undo: &mut DisconnectData,
+        key: &Key,
+        block_height: u32,
+    ) -> Result<(), Error> {
+        let expiry_height = block_height + self.params.name_max_age;
+        record(
+            &self.key_to_expiry_height,
+            wtxn,
+            &mut undo.key_to_expiry_height,
+            key,
+        )?;
+        self.key_to_expiry_height.put(wtxn, key, &expiry_height)?;
+        record(
+            &self.expiry_height_to_keys,
+            wtxn,
+            &mut undo.expiry_height_to_keys,
+            &expiry_height,
+        )?;
+        let mut keys = self
+            .expiry_height_to_keys
+            .get(wtxn, &expiry_height)?
+            .unwrap_or_default();
+        keys.push(*key);
+        self.expiry_height_to_keys
+            .put(wtxn, &expiry_height, &keys)?;
+        Ok(())
+    }
+
+    /// Get the outpoint of the output that last set `key`.
+    fn get_key_outpoint(&self, rtxn: &RoTxn, key: &Key) -> Result<Option<OutPoint>, Error> {
+        let range = (*key, [0; 4])..=(*key, u32::MAX.to_be_bytes());
+        let outpoint = self
+            .key_history
+            .rev_range(rtxn, &range)?
+            .next()
+            .transpose()?
+            .and_then(|(_, entries)| entries.last().map(|entry| entry.outpoint));
+        Ok(outpoint)
+    }
+
+    /// Remove `key` together with its output, and unlink it from its parent
+    /// and subnames.
+    fn remove_name(
+        &self,
+        wtxn: &mut RwTxn,
+        undo: &mut DisconnectData,
+        key: &Key,
+    ) -> Result<(), Error> {
+        record(
+            &self.key_to_expiry_height,
+            wtxn,
+            &mut undo.key_to_expiry_height,
+            key,
+        )?;
+        self.key_to_expiry_height.delete(wtxn, key)?;
+        record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
+        self.key_to_value.delete(wtxn, key)?;
+        record(&self.key_to_records, wtxn, &mut undo.key_to_records, key)?;
+        self.key_to_records.delete(wtxn, key)?;
+        record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+        self.key_to_auction.delete(wtxn, key)?;
+        if let Some(commitment) = self.key_to_commitment.get(wtxn, key)? {
+            record(
+                &self.key_to_commitment,
+                wtxn,
+                &mut undo.key_to_commitment,
+                key,
+            )?;
+            self.key_to_commitment.delete(wtxn, key)?;
+            record(
+                &self.commitment_to_key,
+                wtxn,
+                &mut undo.commitment_to_key,
+                &commitment,
+            )?;
+            self.commitment_to_key.delete(wtxn, &commitment)?;
+        }
+        // Remove the stale name output, so that it can't be used to update
+        // the name after somebody else registers it.
+        if let Some(outpoint) = self.get_key_outpoint(wtxn, key)? {
+            record(&self.utxos, wtxn, &mut undo.utxos, &outpoint)?;
+            self.delete_utxo(wtxn, &outpoint)?;
+        }
+        if let Some(parent) = self.key_to_parent.get(wtxn, key)? {
+            record(&self.key_to_parent, wtxn, &mut undo.key_to_parent, key)?;
+            self.key_to_parent.delete(wtxn, key)?;
+            record(
+                &self.key_to_subnames,
+                wtxn,
+                &mut undo.key_to_subnames,
+                &parent,
+            )?;
+            let mut subnames = self.key_to_subnames.get(wtxn, &parent)?.unwrap_or_default();
+            subnames.retain(|subname| subname != key);
+            if subnames.is_empty() {
+                self.key_to_subnames.delete(wtxn, &parent)?;
+            } else {
+                self.key_to_subnames.put(wtxn, &parent, &subnames)?;
+            }
+        }
+        // Subnames outlive their parent, but whoever registers the parent next
+        // can replace them.
+        let subnames = self.key_to_subnames.get(wtxn, key)?.unwrap_or_default();
+        for subname in &subnames {
+            record(&self.key_to_parent, wtxn, &mut undo.key_to_parent, subname)?;
+            self.key_to_parent.delete(wtxn, subname)?;
+        }
+        record(&self.key_to_subnames, wtxn, &mut undo.key_to_subnames, key)?;
+        self.key_to_subnames.delete(wtxn, key)?;
+        Ok(())
+    }
+
+    fn push_key_history(
+        &self,
+        wtxn: &mut RwTxn,
+        undo: &mut DisconnectData,
+        key: &Key,
+        entry: KeyHistoryEntry,
+    ) -> Result<(), Error> {
+        let history_key = (*key, entry.height.to_be_bytes());
+        record(&self.key_history, wtxn, &mut undo.key_history, &history_key)?;
+        let mut entries = self
+            .key_history
+            .get(wtxn, &history_key)?
+            .unwrap_or_default();
+        entries.push(entry);
+        self.key_history.put(wtxn, &history_key, &entries)?;
+        Ok(())
+    }
+
+    /// Insert a utxo, keeping `address_to_outpoints` up to date.
+    fn put_utxo(
+        &self,
+        wtxn: &mut RwTxn,
+        outpoint: &OutPoint,
+        output: &Output,
+    ) -> Result<(), Error> {
+        self.delete_utxo(wtxn, outpoint)?;
+        self.utxos.put(wtxn, outpoint, output)?;
+        self.address_to_outpoints
+            .put(wtxn, &(output.address, *outpoint), &())?;
+        Ok(())
+    }
+
+    /// Delete a utxo, keeping `address_to_outpoints` up to date.
+    fn delete_utxo(&self, wtxn: &mut RwTxn, outpoint: &OutPoint) -> Result<(), Error> {
+        let output = match self.utxos.get(wtxn, outpoint)? {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+        self.utxos.delete(wtxn, outpoint)?;
+        self.address_to_outpoints
+            .delete(wtxn, &(output.address, *outpoint))?;
+        Ok(())
+    }
+
     pub fn get_pending_withdrawal_bundle(
         &self,
         txn: &RoTxn,
@@ -120,7 +741,54 @@ impl BitNamesState {
         Ok(self.last_withdrawal_bundle.get(txn, &0)?)
     }
 
-    fn collect_withdrawal_bundle(&self, txn: &RoTxn) -> Result<Option<WithdrawalBundle>, Error> {
+    /// Every withdrawal bundle created so far.
+    pub fn get_withdrawal_bundles(
+        &self,
+        rtxn: &RoTxn,
+    ) -> Result<Vec<(bitcoin::Txid, WithdrawalBundleInfo)>, Error> {
+        let bundles = self
+            .withdrawal_bundles
+            .iter(rtxn)?
+            .collect::<Result<_, _>>()?;
+        Ok(bundles)
+    }
+
+    /// Status of the withdrawal `outpoint`, `None` if it was never created or
+    /// was spent by a transaction.
+    pub fn get_withdrawal_status(
+        &self,
+        rtxn: &RoTxn,
+        outpoint: &OutPoint,
+    ) -> Result<Option<WithdrawalStatus>, Error> {
+        Ok(self.withdrawal_statuses.get(rtxn, outpoint)?)
+    }
+
+    /// Last bundle that included the withdrawal `outpoint`.
+    pub fn get_outpoint_withdrawal_bundle(
+        &self,
+        rtxn: &RoTxn,
+        outpoint: &OutPoint,
+    ) -> Result<Option<(bitcoin::Txid, WithdrawalBundleInfo)>, Error> {
+        let txid = match self.outpoint_to_withdrawal_bundle.get(rtxn, outpoint)? {
+            Some(txid) => txid,
+            None => return Ok(None),
+        };
+        let info = self
+            .withdrawal_bundles
+            .get(rtxn, &txid)?
+            .ok_or(BitNamesError::UnknownWithdrawalBundle { txid })?;
+        Ok(Some((txid, info)))
+    }
+
+    /// Build a bundle from pending withdrawals.
+    ///
+    /// Withdrawals are aggregated by mainchain address, and aggregates with
+    /// the highest mainchain fee are included first, ties are broken by value
+    /// and then by address. The bundle only depends on the utxo set.
+    pub fn collect_withdrawal_bundle(
+        &self,
+        txn: &RoTxn,
+    ) -> Result<Option<WithdrawalBundle>, Error> {
         use bitcoin::blockdata::{opcodes, script};
         // Weight of a bundle with 0 outputs.
         const BUNDLE_0_WEIGHT: usize = 504;
@@ -164,12 +832,12 @@ impl BitNamesState {
         }
         let mut aggregated_withdrawals: Vec<_> =
             address_to_aggregated_withdrawal.into_values().collect();
-        aggregated_withdrawals.sort_by_key(|a| std::cmp::Reverse(a.clone()));
+        aggregated_withdrawals.sort_by(|a, b| b.cmp(a));
         let mut fee = 0;
         let mut spent_utxos = HashMap::<OutPoint, Output>::new();
         let mut bundle_outputs = vec![];
         for aggregated in &aggregated_withdrawals {
-            if bundle_outputs.len() > MAX_BUNDLE_OUTPUTS {
+            if bundle_outputs.len() >= MAX_BUNDLE_OUTPUTS {
                 break;
             }
             let bundle_output = bitcoin::TxOut {
@@ -208,7 +876,7 @@ impl BitNamesState {
             script_pubkey: script,
         };
         // Create inputs commitment.
-        let inputs: Vec<OutPoint> = spent_utxos.keys().copied().collect();
+        let inputs = sorted_outpoints(&spent_utxos);
         let commitment = hash(&inputs);
         let script = script::Builder::new()
             .push_opcode(opcodes::all::OP_RETURN)
@@ -248,8 +916,67 @@ impl BitNamesState {
         Ok(self.headers.last(rtxn)?.unwrap())
     }
 
+    pub fn get_header(&self, rtxn: &RoTxn, height: u32) -> Result<Option<Header>, Error> {
+        Ok(self.headers.get(rtxn, &height)?)
+    }
+
+    /// Body of the block at `height`, `None` for the genesis block and blocks
+    /// connected before bodies were stored.
+    pub fn get_body(&self, rtxn: &RoTxn, height: u32) -> Result<Option<Body>, Error> {
+        Ok(self.bodies.get(rtxn, &height)?)
+    }
+
+    /// Confirmed transaction `txid`, `None` if it isn't indexed.
+    pub fn get_transaction(
+        &self,
+        rtxn: &RoTxn,
+        txid: &Txid,
+    ) -> Result<Option<ConfirmedTransaction>, Error> {
+        let location = match self.txid_to_location.get(rtxn, txid)? {
+            Some(location) => location,
+            None => return Ok(None),
+        };
+        let height = location.height;
+        let (header, body) = match (
+            self.headers.get(rtxn, &height)?,
+            self.bodies.get(rtxn, &height)?,
+        ) {
+            (Some(header), Some(body)) => (header, body),
+            _ => return Ok(None),
+        };
+        let index = location.index as usize;
+        let transaction = body.transactions[index].clone();
+        // Authorizations of all transactions are concatenated in the body.
+        let start: usize = body.transactions[..index]
+            .iter()
+            .map(|transaction| transaction.inputs.len())
+            .sum();
+        let authorizations = body.authorizations[start..start + transaction.inputs.len()].to_vec();
+        let (best_height, _) = self.get_best_header(rtxn)?;
+        Ok(Some(ConfirmedTransaction {
+            transaction: AuthorizedTransaction {
+                transaction,
+                authorizations,
+            },
+            height,
+            block_hash: header.block_hash(),
+            index: location.index,
+            confirmations: best_height - height + 1,
+            spent_utxos: location.spent_utxos,
+        }))
+    }
+
+    pub fn get_block_height(
+        &self,
+        rtxn: &RoTxn,
+        block_hash: &BlockHash,
+    ) -> Result<Option<u32>, Error> {
+        Ok(self.block_hash_to_height.get(rtxn, block_hash)?)
+    }
+
     pub fn validate_body(&self, rtxn: &RoTxn, body: &Body) -> Result<u64, Error> {
-        let (block_height, _) = self.headers.last(rtxn)?.unwrap();
+        let (best_block_height, _) = self.headers.last(rtxn)?.unwrap();
+        let block_height = best_block_height + 1;
         verify_authorizations(body)?;
         let inputs: Vec<OutPoint> = body
             .transactions
@@ -257,13 +984,72 @@ impl BitNamesState {
             .flat_map(|transaction| transaction.inputs.iter())
             .copied()
             .collect();
-        let spent_utxos = self.get_utxos(rtxn, &inputs)?;
-        {
-            let mut index = 0;
-            for transaction in &body.transactions {
-                let spent_utxos = &spent_utxos[index..transaction.inputs.len()];
-                self.validate_transaction_pure(rtxn, spent_utxos, block_height, transaction)?;
-                index += transaction.inputs.len();
+        let mut spent = HashSet::<OutPoint>::new();
+        for outpoint in &inputs {
+            if !spent.insert(*outpoint) {
+                Err(BitNamesError::DoubleSpend {
+                    outpoint: *outpoint,
+                })?;
+            }
+        }
+        // Transactions can spend outputs of earlier transactions in the body.
+        let mut body_utxos = HashMap::<OutPoint, Output>::new();
+        let mut spent_utxos = vec![];
+        let mut fees: u64 = 0;
+        for transaction in &body.transactions {
+            let transaction_spent_utxos =
+                self.get_unconfirmed_utxos(rtxn, &body_utxos, &transaction.inputs)?;
+            let fee = self.validate_transaction_pure(
+                rtxn,
+                &transaction_spent_utxos,
+                block_height,
+                transaction,
+            )?;
+            fees = fees.checked_add(fee).ok_or(BitNamesError::FeesOverflow)?;
+            let txid = transaction.txid();
+            for (vout, output) in transaction.outputs.iter().enumerate() {
+                let outpoint = OutPoint::Regular {
+                    txid,
+                    vout: vout as u32,
+                };
+                body_utxos.insert(outpoint, output.clone());
+            }
+            spent_utxos.extend(transaction_spent_utxos);
+        }
+        // The coinbase pays collected fees to the block producer, it can't
+        // create names.
+        let mut coinbase_value: u64 = 0;
+        for output in &body.coinbase {
+            match output.content {
+                Content::Value(value) => {
+                    coinbase_value = coinbase_value
+                        .checked_add(value)
+                        .ok_or(BitNamesError::CoinbaseValueOverflow)?;
+                }
+                _ => Err(BitNamesError::InvalidCoinbaseOutput)?,
+            }
+        }
+        if coinbase_value > fees {
+            Err(BitNamesError::CoinbaseExceedsFees {
+                coinbase_value,
+                fees,
+            })?;
+        }
+        // Transactions are validated separately, make sure they don't
+        // register the same name or create the same commitment.
+        let mut keys = HashSet::<Key>::new();
+        let mut commitments = HashSet::<Commitment>::new();
+        for transaction in &body.transactions {
+            let (transaction_keys, transaction_commitments) = transaction_claims(transaction);
+            for key in transaction_keys {
+                if !keys.insert(key) {
+                    Err(BitNamesError::DuplicateNameRegistration { key })?;
+                }
+            }
+            for commitment in transaction_commitments {
+                if !commitments.insert(commitment) {
+                    Err(BitNamesError::DuplicateCommitment { commitment })?;
+                }
             }
         }
         Ok(validate_body(spent_utxos.as_slice(), body)?)
@@ -275,7 +1061,8 @@ impl BitNamesState {
         spent_utxos: &[Output],
         block_height: u32,
         transaction: &Transaction,
-    ) -> Result<(), Error> {
+    ) -> Result<u64, Error> {
+        let fee = validate_transaction(spent_utxos, transaction)?;
         let spent_commitments: HashSet<Commitment> = spent_utxos
             .iter()
             .filter_map(|utxo| match utxo.content {
@@ -283,14 +1070,88 @@ impl BitNamesState {
                 _ => None,
             })
             .collect();
-        let spent_keys: HashSet<Key> = spent_utxos
+        let mut spent_keys: HashSet<Key> = spent_utxos
             .iter()
             .filter_map(|utxo| match utxo.content {
                 Content::Custom(BitNamesOutput::Reveal { key, .. }) => Some(key),
                 Content::Custom(BitNamesOutput::KeyValue { key, .. }) => Some(key),
+                Content::Custom(BitNamesOutput::Transfer { key }) => Some(key),
+                Content::Custom(BitNamesOutput::KeyRecords { key, .. }) => Some(key),
+                Content::Custom(BitNamesOutput::Subname { parent, label }) => {
+                    Some(subname_key(&parent, &label))
+                }
                 _ => None,
             })
             .collect();
+        let mut auction_price = 0;
+        for (outpoint, utxo) in transaction.inputs.iter().zip(spent_utxos) {
+            match utxo.content {
+                // Bids can only be spent by revealing them, bids that are not
+                // revealed in time are forfeited. Bids of auctions cancelled by
+                // a reveal of the name can be spent freely.
+                Content::Custom(BitNamesOutput::Bid {
+                    key,
+                    blinded_bid,
+                    deposit,
+                }) => {
+                    let auction = match self.key_to_auction.get(txn, &key)? {
+                        Some(auction) if auction.bids.contains(outpoint) => auction,
+                        _ => continue,
+                    };
+                    let reveal_height = auction.start_height + AUCTION_BIDDING_PERIOD;
+                    if block_height < reveal_height || block_height > auction_end_height(&auction) {
+                        Err(BitNamesError::NotInRevealPeriod { key })?;
+                    }
+                    let revealed = transaction.outputs.iter().any(|output| {
+                        matches!(
+                            output.content,
+                            Content::Custom(BitNamesOutput::RevealedBid {
+                                key: revealed_key,
+                                salt,
+                                amount,
+                                deposit: revealed_deposit,
+                            }) if revealed_key == key
+                                && revealed_deposit == deposit
+                                && blind_bid(&key, amount, &salt) == blinded_bid
+                        )
+                    });
+                    if !revealed {
+                        Err(BitNamesError::BidNotRevealed { key })?;
+                    }
+                }
+                // Revealed bids are locked until the auction ends, the winning
+                // bid then holds the name and spending it pays the price.
+                Content::Custom(BitNamesOutput::RevealedBid { key, .. }) => {
+                    if let Some(auction) = self.key_to_auction.get(txn, &key)? {
+                        let end_height = auction_end_height(&auction);
+                        if block_height <= end_height {
+                            Err(BitNamesError::AuctionNotEnded { key, end_height })?;
+                        }
+                        if auction.winner.map(|(_, winner)| winner) == Some(*outpoint) {
+                            spent_keys.insert(key);
+                            auction_price += auction.price;
+                            if fee < auction_price {
+                                Err(BitNamesError::AuctionPriceNotPaid {
+                                    key,
+                                    price: auction.price,
+                                })?;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for key in &spent_keys {
+            if let Some(expiry_height) = self.key_to_expiry_height.get(txn, key)? {
+                if block_height >= expiry_height {
+                    Err(BitNamesError::NameExpired {
+                        key: *key,
+                        expiry_height,
+                    })?;
+                }
+            }
+        }
         for commitment in &spent_commitments {
             let height = self.get_commitment_height(txn, commitment)?;
             if block_height - height > COMMITMENT_MAX_AGE {
@@ -300,6 +1161,24 @@ impl BitNamesState {
                 })?;
             }
         }
+        // Fees already used to pay for auctions can't pay for registrations.
+        let reveals = transaction
+            .outputs
+            .iter()
+            .filter(|output| {
+                matches!(
+                    output.content,
+                    Content::Custom(BitNamesOutput::Reveal { .. })
+                )
+            })
+            .count() as u64;
+        let registration_fee = reveals * self.params.registration_fee;
+        if fee - auction_price < registration_fee {
+            Err(BitNamesError::RegistrationFeeNotPaid {
+                fee: fee - auction_price,
+                registration_fee,
+            })?;
+        }
         for output in &transaction.outputs {
             match output.content {
                 Content::Custom(BitNamesOutput::Reveal { salt, key }) => {
@@ -311,6 +1190,17 @@ impl BitNamesState {
                             commitment,
                         })?;
                     }
+                    // Names committed to before an auction for them started can
+                    // still be revealed, which cancels the auction, so that
+                    // bids can't front-run reveals.
+                    if let Some(auction) = self.key_to_auction.get(txn, &key)? {
+                        let commitment_height = self.get_commitment_height(txn, &commitment)?;
+                        if commitment_height >= auction.start_height
+                            || block_height > auction_end_height(&auction)
+                        {
+                            Err(BitNamesError::NameUnderAuction { key })?;
+                        }
+                    }
                     if self.key_to_value.get(txn, &key)?.is_some() {
                         let commitment_height = self.get_commitment_height(txn, &commitment)?;
                         let prev_commitment_height = self.get_key_height(txn, &key)?;
@@ -323,11 +1213,117 @@ impl BitNamesState {
                         }
                     }
                 }
-                Content::Custom(BitNamesOutput::KeyValue { key, .. }) => {
+                Content::Custom(BitNamesOutput::KeyValue { key, .. })
+                | Content::Custom(BitNamesOutput::Transfer { key }) => {
                     if !spent_keys.contains(&key) {
                         Err(BitNamesError::InvalidKey { key })?;
                     }
                 }
+                Content::Custom(BitNamesOutput::KeyRecords { key, ref records }) => {
+                    if !spent_keys.contains(&key) {
+                        Err(BitNamesError::InvalidKey { key })?;
+                    }
+                    if records.len() > MAX_RECORDS {
+                        Err(BitNamesError::TooManyRecords {
+                            key,
+                            records: records.len(),
+                            max_records: MAX_RECORDS,
+                        })?;
+                    }
+                    for record in records {
+                        if let Record::Txt(txt) = record {
+                            if txt.len() > MAX_TXT_RECORD_LENGTH {
+                                Err(BitNamesError::TxtRecordTooLong {
+                                    key,
+                                    length: txt.len(),
+                                    max_length: MAX_TXT_RECORD_LENGTH,
+                                })?;
+                            }
+                        }
+                    }
+                    let size = bincode::serialized_size(records)? as usize;
+                    if size > MAX_RECORDS_SIZE {
+                        Err(BitNamesError::RecordsTooLarge {
+                            key,
+                            size,
+                            max_size: MAX_RECORDS_SIZE,
+                        })?;
+                    }
+                }
+                Content::Custom(BitNamesOutput::Subname { parent, label }) => {
+                    // Only the owner of the parent name can create subnames.
+                    if !spent_keys.contains(&parent) {
+                        Err(BitNamesError::ParentNameNotSpent { parent })?;
+                    }
+                    // Names revealed under the key of a subname are replaced, so
+                    // that subnames can't be squatted before they are created.
+                    let key = subname_key(&parent, &label);
+                    if self.key_to_value.get(txn, &key)?.is_some()
+                        && self.key_to_parent.get(txn, &key)?.is_some()
+                    {
+                        Err(BitNamesError::SubnameAlreadyRegistered { key, parent })?;
+                    }
+                }
+                Content::Custom(BitNamesOutput::Bid { key, deposit, .. }) => {
+                    // Forfeited deposits make it costly to block names with
+                    // bids that are never revealed.
+                    if deposit < self.params.registration_fee {
+                        Err(BitNamesError::BidDepositTooLow {
+                            key,
+                            deposit,
+                            min_deposit: self.params.registration_fee,
+                        })?;
+                    }
+                    if self.key_to_value.get(txn, &key)?.is_some() {
+                        Err(BitNamesError::NameNotAvailable { key })?;
+                    }
+                    if let Some(auction) = self.key_to_auction.get(txn, &key)? {
+                        if block_height >= auction.start_height + AUCTION_BIDDING_PERIOD {
+                            Err(BitNamesError::BiddingClosed { key })?;
+                        }
+                    }
+                }
+                Content::Custom(BitNamesOutput::RevealedBid {
+                    key,
+                    salt,
+                    amount,
+                    deposit,
+                }) => {
+                    if amount > deposit {
+                        Err(BitNamesError::BidExceedsDeposit {
+                            key,
+                            amount,
+                            deposit,
+                        })?;
+                    }
+                    // Only bids placed in the current auction can be revealed.
+                    let bids = self
+                        .key_to_auction
+                        .get(txn, &key)?
+                        .map(|auction| auction.bids)
+                        .unwrap_or_default();
+                    let spent_bid =
+                        transaction
+                            .inputs
+                            .iter()
+                            .zip(spent_utxos)
+                            .any(|(outpoint, utxo)| {
+                                bids.contains(outpoint)
+                                    && matches!(
+                                        utxo.content,
+                                        Content::Custom(BitNamesOutput::Bid {
+                                            key: bid_key,
+                                            blinded_bid,
+                                            deposit: bid_deposit,
+                                        }) if bid_key == key
+                                            && bid_deposit == deposit
+                                            && blinded_bid == blind_bid(&key, amount, &salt)
+                                    )
+                            });
+                    if !spent_bid {
+                        Err(BitNamesError::InvalidBidReveal { key })?;
+                    }
+                }
                 Content::Custom(BitNamesOutput::Commitment(commitment)) => {
                     if self.commitment_to_outpoint.get(txn, &commitment)?.is_some() {
                         Err(BitNamesError::CommitmentAlreadyExists { commitment })?;
@@ -336,7 +1332,7 @@ impl BitNamesState {
                 _ => {}
             }
         }
-        Ok(())
+        Ok(fee)
     }
 
     fn get_commitment_height(&self, txn: &RoTxn, commitment: &Commitment) -> Result<u32, Error> {
@@ -363,20 +1359,36 @@ impl BitNamesState {
         rtxn: &RoTxn,
         transaction: &Transaction,
     ) -> Result<u64, Error> {
-        let spent_utxos = self.get_utxos(rtxn, &transaction.inputs)?;
+        self.validate_unconfirmed_transaction(rtxn, &HashMap::new(), transaction)
+    }
+
+    /// Validate a transaction that can spend `unconfirmed` outputs, as if it
+    /// was included in the next block after the transactions creating them.
+    pub fn validate_unconfirmed_transaction(
+        &self,
+        rtxn: &RoTxn,
+        unconfirmed: &HashMap<OutPoint, Output>,
+        transaction: &Transaction,
+    ) -> Result<u64, Error> {
+        let spent_utxos = self.get_unconfirmed_utxos(rtxn, unconfirmed, &transaction.inputs)?;
         // TODO: Add an error for this case, don't unwrap.
         let (best_block_height, _) = self.headers.last(rtxn)?.unwrap();
         // Will this transaction be valid, if included in next block?
-        self.validate_transaction_pure(rtxn, &spent_utxos, best_block_height + 1, transaction)?;
-        Ok(validate_transaction(&spent_utxos, transaction)?)
+        self.validate_transaction_pure(rtxn, &spent_utxos, best_block_height + 1, transaction)
     }
 
+    /// Validate a block on top of the best block, except for the state root
+    /// in its header.
+    ///
+    /// The state root is checked by `connect_block`. It depends on every
+    /// name the block updates or expires, and the state tree is only updated
+    /// by applying the block in a write transaction, which `validate_block`
+    /// doesn't have.
     pub fn validate_block(
         &self,
         rtxn: &RoTxn,
         header: &Header,
         body: &Body,
-        // TODO: Validate two way peg data.
         two_way_peg_data: &TwoWayPegData,
     ) -> Result<(), Error> {
         let (_, prev_header) = self.headers.last(rtxn)?.unwrap();
@@ -388,25 +1400,367 @@ impl BitNamesState {
         if header.merkle_root != merkle_root {
             Err(HeaderError::InvalidMerkleRoot)?;
         }
+        validate_body_size(body)?;
+        self.validate_two_way_peg_data(rtxn, two_way_peg_data)?;
         self.validate_body(rtxn, body)?;
         Ok(())
     }
 
-    pub fn disconnect_block(
+    pub fn validate_two_way_peg_data(
         &self,
-        _wtxn: &mut RwTxn,
-        _disconnect_data: &DisconnectData,
+        rtxn: &RoTxn,
+        two_way_peg_data: &TwoWayPegData,
     ) -> Result<(), Error> {
-        todo!();
+        // Deposits must come from a mainchain block after the last processed
+        // one.
+        let last_deposit_block = self.last_deposit_block.get(rtxn, &0)?;
+        match (two_way_peg_data.deposit_block, last_deposit_block) {
+            (Some((deposit_block_hash, _)), Some((last_deposit_block_hash, _)))
+                if deposit_block_hash == last_deposit_block_hash =>
+            {
+                Err(BitNamesError::DepositBlockAlreadyProcessed { deposit_block_hash })?;
+            }
+            (
+                Some((deposit_block_hash, deposit_block_height)),
+                Some((_, last_deposit_block_height)),
+            ) if deposit_block_height <= last_deposit_block_height => {
+                Err(BitNamesError::DepositBlockOutOfOrder {
+                    deposit_block_hash,
+                    deposit_block_height,
+                    last_deposit_block_height,
+                })?;
+            }
+            (Some(_), _) => {}
+            (None, _) => {
+                if !two_way_peg_data.deposits.is_empty() {
+                    Err(BitNamesError::MissingDepositBlockHash)?;
+                }
+            }
+        }
+        for (outpoint, output) in &two_way_peg_data.deposits {
+            if !matches!(outpoint, OutPoint::Deposit(_)) {
+                Err(BitNamesError::InvalidDepositOutPoint {
+                    outpoint: *outpoint,
+                })?;
+            }
+            if !matches!(output.content, Content::Value(_)) {
+                Err(BitNamesError::InvalidDepositOutput {
+                    outpoint: *outpoint,
+                })?;
+            }
+            if self.utxos.get(rtxn, outpoint)?.is_some() {
+                Err(BitNamesError::DepositAlreadyExists {
+                    outpoint: *outpoint,
+                })?;
+            }
+        }
+        // Bundle statuses can only refer to the pending bundle.
+        let pending_txid = self
+            .last_withdrawal_bundle
+            .get(rtxn, &0)?
+            .map(|bundle| bundle.transaction.txid());
+        for txid in two_way_peg_data.bundle_statuses.keys() {
+            if Some(*txid) != pending_txid {
+                Err(BitNamesError::UnknownWithdrawalBundle { txid: *txid })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Roll back the tip block using the undo data recorded by
+    /// `connect_block`.
+    pub fn disconnect_block(&self, wtxn: &mut RwTxn) -> Result<(), Error> {
+        let (block_height, _) = self.headers.last(wtxn)?.unwrap();
+        let disconnect_data = self
+            .disconnect_data
+            .get(wtxn, &block_height)?
+            .ok_or(BitNamesError::DisconnectDataNotFound { block_height })?;
+
+        for (outpoint, output) in &disconnect_data.utxos {
+            match output {
+                Some(output) => self.put_utxo(wtxn, outpoint, output)?,
+                None => self.delete_utxo(wtxn, outpoint)?,
+            }
+        }
+        restore(&self.key_to_value, wtxn, &disconnect_data.key_to_value)?;
+        for key in disconnect_data.key_to_value.keys() {
+            self.update_state_tree(wtxn, key)?;
+        }
+        restore(&self.key_to_records, wtxn, &disconnect_data.key_to_records)?;
+        restore(&self.key_to_parent, wtxn, &disconnect_data.key_to_parent)?;
+        restore(
+            &self.key_to_subnames,
+            wtxn,
+            &disconnect_data.key_to_subnames,
+        )?;
+        restore(
+            &self.commitment_to_height,
+            wtxn,
+            &disconnect_data.commitment_to_height,
+        )?;
+        restore(
+            &self.commitment_to_outpoint,
+            wtxn,
+            &disconnect_data.commitment_to_outpoint,
+        )?;
+        restore(
+            &self.height_to_commitments,
+            wtxn,
+            &disconnect_data.height_to_commitments,
+        )?;
+        restore(
+            &self.key_to_commitment,
+            wtxn,
+            &disconnect_data.key_to_commitment,
+        )?;
+        restore(
+            &self.commitment_to_key,
+            wtxn,
+            &disconnect_data.commitment_to_key,
+        )?;
+        restore(&self.key_history, wtxn, &disconnect_data.key_history)?;
+        restore(&self.key_to_auction, wtxn, &disconnect_data.key_to_auction)?;
+        restore(
+            &self.withdrawal_bundles,
+            wtxn,
+            &disconnect_data.withdrawal_bundles,
+        )?;
+        restore(
+            &self.outpoint_to_withdrawal_bundle,
+            wtxn,
+            &disconnect_data.outpoint_to_withdrawal_bundle,
+        )?;
+        restore(
+            &self.withdrawal_statuses,
+            wtxn,
+            &disconnect_data.withdrawal_statuses,
+        )?;
+        restore(
+            &self.auction_end_height_to_keys,
+            wtxn,
+            &disconnect_data.auction_end_height_to_keys,
+        )?;
+        restore(
+            &self.key_to_expiry_height,
+            wtxn,
+            &disconnect_data.key_to_expiry_height,
+        )?;
+        restore(
+            &self.expiry_height_to_keys,
+            wtxn,
+            &disconnect_data.expiry_height_to_keys,
+        )?;
+
+        match &disconnect_data.last_withdrawal_bundle {
+            Some(bundle) => self.last_withdrawal_bundle.put(wtxn, &0, bundle)?,
+            None => {
+                self.last_withdrawal_bundle.delete(wtxn, &0)?;
+            }
+        }
+        match &disconnect_data.last_withdrawal_bundle_failure_height {
+            Some(height) => self
+                .last_withdrawal_bundle_failure_height
+                .put(wtxn, &0, height)?,
+            None => {
+                self.last_withdrawal_bundle_failure_height
+                    .delete(wtxn, &0)?;
+            }
+        }
+        match &disconnect_data.last_deposit_block {
+            Some(deposit_block) => self.last_deposit_block.put(wtxn, &0, deposit_block)?,
+            None => {
+                self.last_deposit_block.delete(wtxn, &0)?;
+            }
+        }
+
+        self.disconnect_data.delete(wtxn, &block_height)?;
+        if let Some(header) = self.headers.get(wtxn, &block_height)? {
+            self.block_hash_to_height
+                .delete(wtxn, &header.block_hash())?;
+        }
+        if let Some(body) = self.bodies.get(wtxn, &block_height)? {
+            for transaction in &body.transactions {
+                self.txid_to_location.delete(wtxn, &transaction.txid())?;
+            }
+        }
+        self.bodies.delete(wtxn, &block_height)?;
+        self.headers.delete(wtxn, &block_height)?;
+        Ok(())
+    }
+
+    /// Every database, in the order they are written to snapshots. Snapshots
+    /// leave out `LOCAL_DATABASES`.
+    pub fn raw_databases(&self) -> Vec<(&'static str, Database<ByteSlice, ByteSlice>)> {
+        vec![
+            ("key_to_value", self.key_to_value.remap_types()),
+            ("key_to_records", self.key_to_records.remap_types()),
+            (
+                "commitment_to_height",
+                self.commitment_to_height.remap_types(),
+            ),
+            (
+                "commitment_to_outpoint",
+                self.commitment_to_outpoint.remap_types(),
+            ),
+            (
+                "height_to_commitments",
+                self.height_to_commitments.remap_types(),
+            ),
+            ("key_to_commitment", self.key_to_commitment.remap_types()),
+            ("commitment_to_key", self.commitment_to_key.remap_types()),
+            ("key_history", self.key_history.remap_types()),
+            ("key_to_parent", self.key_to_parent.remap_types()),
+            ("key_to_subnames", self.key_to_subnames.remap_types()),
+            ("key_to_auction", self.key_to_auction.remap_types()),
+            (
+                "auction_end_height_to_keys",
+                self.auction_end_height_to_keys.remap_types(),
+            ),
+            (
+                "key_to_expiry_height",
+                self.key_to_expiry_height.remap_types(),
+            ),
+            (
+                "expiry_height_to_keys",
+                self.expiry_height_to_keys.remap_types(),
+            ),
+            ("state_tree", self.state_tree.remap_types()),
+            (
+                "last_withdrawal_bundle",
+                self.last_withdrawal_bundle.remap_types(),
+            ),
+            (
+                "last_withdrawal_bundle_failure_height",
+                self.last_withdrawal_bundle_failure_height.remap_types(),
+            ),
+            ("last_deposit_block", self.last_deposit_block.remap_types()),
+            ("withdrawal_bundles", self.withdrawal_bundles.remap_types()),
+            (
+                "outpoint_to_withdrawal_bundle",
+                self.outpoint_to_withdrawal_bundle.remap_types(),
+            ),
+            (
+                "withdrawal_statuses",
+                self.withdrawal_statuses.remap_types(),
+            ),
+            ("utxos", self.utxos.remap_types()),
+            (
+                "address_to_outpoints",
+                self.address_to_outpoints.remap_types(),
+            ),
+            ("headers", self.headers.remap_types()),
+            ("bodies", self.bodies.remap_types()),
+            (
+                "block_hash_to_height",
+                self.block_hash_to_height.remap_types(),
+            ),
+            ("txid_to_location", self.txid_to_location.remap_types()),
+            ("disconnect_data", self.disconnect_data.remap_types()),
+            ("metadata", self.metadata.remap_types()),
+        ]
+    }
+
+    /// Write the contents of every database at the best block to `writer`.
+    ///
+    /// Integer keys are stored in native byte order, so snapshots can only be
+    /// imported on machines with the same endianness.
+    pub fn export_snapshot(
+        &self,
+        rtxn: &RoTxn,
+        mut writer: impl std::io::Write,
+    ) -> Result<(), Error> {
+        let (height, header) = self.get_best_header(rtxn)?;
+        let databases = self
+            .raw_databases()
+            .into_iter()
+            .filter(|(name, _)| !LOCAL_DATABASES.contains(name))
+            .map(|(name, db)| {
+                let entries = db
+                    .iter(rtxn)?
+                    .map(|item| {
+                        let (key, value) = item?;
+                        Ok((key.to_vec(), value.to_vec()))
+                    })
+                    .collect::<Result<_, Error>>()?;
+                Ok((name.to_string(), entries))
+            })
+            .collect::<Result<_, Error>>()?;
+        let snapshot = Snapshot {
+            height,
+            block_hash: header.block_hash(),
+            databases,
+        };
+        let contents = bincode::serialize(&snapshot)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_be_bytes())?;
+        writer.write_all(&contents)?;
+        writer.write_all(&hash(&contents))?;
+        Ok(())
+    }
+
+    /// Replace the contents of every database with a snapshot written by
+    /// `export_snapshot`, returns the height and hash of its best block.
+    ///
+    /// Bodies and transaction locations of blocks in the snapshot are not
+    /// available, block hashes are indexed again from the headers.
+    pub fn import_snapshot(
+        &self,
+        wtxn: &mut RwTxn,
+        mut reader: impl std::io::Read,
+    ) -> Result<(u32, BlockHash), Error> {
+        let mut version = [0; 4];
+        reader.read_exact(&mut version)?;
+        let version = u32::from_be_bytes(version);
+        if version != SNAPSHOT_VERSION {
+            Err(SnapshotError::UnsupportedVersion { version })?;
+        }
+        let mut contents = vec![];
+        reader.read_to_end(&mut contents)?;
+        if contents.len() < 32 {
+            Err(SnapshotError::InvalidChecksum)?;
+        }
+        let checksum = contents.split_off(contents.len() - 32);
+        if checksum[..] != hash(&contents)[..] {
+            Err(SnapshotError::InvalidChecksum)?;
+        }
+        let snapshot: Snapshot = bincode::deserialize(&contents)?;
+        // Snapshots of older schema versions can lack newer databases.
+        let databases: HashMap<_, _> = self.raw_databases().into_iter().collect();
+        for (name, _) in &snapshot.databases {
+            if !databases.contains_key(name.as_str()) || LOCAL_DATABASES.contains(&name.as_str()) {
+                Err(SnapshotError::UnknownDatabase { name: name.clone() })?;
+            }
+        }
+        for db in databases.values() {
+            db.clear(wtxn)?;
+        }
+        for (name, entries) in &snapshot.databases {
+            let db = &databases[name.as_str()];
+            for (key, value) in entries {
+                db.put(wtxn, key, value)?;
+            }
+        }
+        // Snapshots include the schema version, upgrade older ones.
+        self.migrate(wtxn)?;
+        self.index_block_hashes(wtxn)?;
+        let (height, header) = self.get_best_header(wtxn)?;
+        if height != snapshot.height || header.block_hash() != snapshot.block_hash {
+            Err(SnapshotError::InvalidBestBlock)?;
+        }
+        Ok((height, snapshot.block_hash))
     }
 
     pub fn get_last_deposit_block_hash(
         &self,
         rtxn: &RoTxn,
     ) -> Result<Option<bitcoin::BlockHash>, Error> {
-        Ok(self.last_deposit_block.get(&rtxn, &0)?)
+        Ok(self
+            .last_deposit_block
+            .get(rtxn, &0)?
+            .map(|(block_hash, _)| block_hash))
     }
 
+    /// Connect a block validated with `validate_block`, and check the state
+    /// root in its header, which is only known once the block is connected.
     pub fn connect_block(
         &self,
         wtxn: &mut RwTxn,
@@ -414,17 +1768,62 @@ impl BitNamesState {
         body: &Body,
         two_way_peg_data: &TwoWayPegData,
     ) -> Result<(), Error> {
+        self.apply_block(wtxn, header, body, two_way_peg_data)?;
+        if header.state_root != self.get_state_root(wtxn)? {
+            Err(HeaderError::InvalidStateRoot)?;
+        }
+        Ok(())
+    }
+
+    /// Root of the state tree after connecting a block with `body` on top of
+    /// the best block, for the header of a new block.
+    ///
+    /// Names don't depend on two way peg data, so none is needed.
+    pub fn compute_state_root(&self, env: &heed::Env, body: &Body) -> Result<Hash, Error> {
+        let mut wtxn = env.write_txn()?;
+        let (_, prev_header) = self.get_best_header(&wtxn)?;
+        let header = Header {
+            prev_side_block_hash: prev_header.block_hash(),
+            prev_main_block_hash: prev_header.prev_main_block_hash,
+            merkle_root: body.compute_merkle_root(),
+            state_root: Hash::default(),
+        };
+        self.apply_block(&mut wtxn, &header, body, &TwoWayPegData::default())?;
+        // `wtxn` is aborted when it is dropped.
+        self.get_state_root(&wtxn)
+    }
+
+    fn apply_block(
+        &self,
+        wtxn: &mut RwTxn,
+        header: &Header,
+        body: &Body,
+        two_way_peg_data: &TwoWayPegData,
+    ) -> Result<(), Error> {
+        let mut undo = DisconnectData {
+            last_withdrawal_bundle: self.last_withdrawal_bundle.get(wtxn, &0)?,
+            last_withdrawal_bundle_failure_height: self
+                .last_withdrawal_bundle_failure_height
+                .get(wtxn, &0)?,
+            last_deposit_block: self.last_deposit_block.get(wtxn, &0)?,
+            ..Default::default()
+        };
+
         // Connect header.
         let (block_height, _) = self.headers.last(wtxn)?.unwrap();
         self.headers
             .append(wtxn, &(block_height + 1), &header.clone())?;
+        self.bodies.put(wtxn, &(block_height + 1), body)?;
+        self.block_hash_to_height
+            .put(wtxn, &header.block_hash(), &(block_height + 1))?;
 
         // Handle deposits.
-        if let Some(deposit_block_hash) = two_way_peg_data.deposit_block_hash {
-            self.last_deposit_block.put(wtxn, &0, &deposit_block_hash)?;
+        if let Some(deposit_block) = &two_way_peg_data.deposit_block {
+            self.last_deposit_block.put(wtxn, &0, deposit_block)?;
         }
         for (outpoint, deposit) in &two_way_peg_data.deposits {
-            self.utxos.put(wtxn, outpoint, deposit)?;
+            record(&self.utxos, wtxn, &mut undo.utxos, outpoint)?;
+            self.put_utxo(wtxn, outpoint, deposit)?;
         }
 
         // Handle withdrawals.
@@ -432,14 +1831,22 @@ impl BitNamesState {
             .last_withdrawal_bundle_failure_height
             .get(wtxn, &0)?
             .unwrap_or(0);
-        if (block_height + 1) - last_withdrawal_bundle_failure_height
-            > Self::WITHDRAWAL_BUNDLE_FAILURE_GAP
+        // Only one bundle can be pending at a time.
+        let pending_bundle = self.last_withdrawal_bundle.get(wtxn, &0)?;
+        if pending_bundle.is_none()
+            && (block_height + 1) - last_withdrawal_bundle_failure_height
+                > Self::WITHDRAWAL_BUNDLE_FAILURE_GAP
         {
             if let Some(bundle) = self.collect_withdrawal_bundle(wtxn)? {
+                let txid = bundle.transaction.txid();
                 for outpoint in bundle.spent_utxos.keys() {
-                    self.utxos.delete(wtxn, outpoint)?;
+                    record(&self.utxos, wtxn, &mut undo.utxos, outpoint)?;
+                    self.delete_utxo(wtxn, outpoint)?;
+                    let status = WithdrawalStatus::Bundled { txid };
+                    self.put_withdrawal_status(wtxn, &mut undo, outpoint, &status)?;
                 }
                 self.last_withdrawal_bundle.put(wtxn, &0, &bundle)?;
+                self.put_withdrawal_bundle_info(wtxn, &mut undo, &bundle, block_height + 1)?;
             }
         }
         for (txid, status) in &two_way_peg_data.bundle_statuses {
@@ -455,33 +1862,135 @@ impl BitNamesState {
                             &(block_height + 1),
                         )?;
                         for (outpoint, output) in &bundle.spent_utxos {
-                            self.utxos.put(wtxn, outpoint, output)?;
+                            record(&self.utxos, wtxn, &mut undo.utxos, outpoint)?;
+                            self.put_utxo(wtxn, outpoint, output)?;
                         }
                     }
-                    WithdrawalBundleStatus::Confirmed => {
-                        self.last_withdrawal_bundle.delete(wtxn, &0)?;
-                    }
+                    WithdrawalBundleStatus::Confirmed { .. } => {}
+                }
+                let withdrawal_status = withdrawal_status(*txid, Some(*status));
+                for outpoint in bundle.spent_utxos.keys() {
+                    self.put_withdrawal_status(wtxn, &mut undo, outpoint, &withdrawal_status)?;
                 }
+                self.last_withdrawal_bundle.delete(wtxn, &0)?;
+                record(
+                    &self.withdrawal_bundles,
+                    wtxn,
+                    &mut undo.withdrawal_bundles,
+                    txid,
+                )?;
+                let mut info = self
+                    .withdrawal_bundles
+                    .get(wtxn, txid)?
+                    .ok_or(BitNamesError::UnknownWithdrawalBundle { txid: *txid })?;
+                info.status = Some(*status);
+                self.withdrawal_bundles.put(wtxn, txid, &info)?;
             }
         }
 
         // Connect body.
         let block_height = block_height + 1;
-        for transaction in &body.transactions {
+        let merkle_root = body.compute_merkle_root();
+        for (vout, output) in body.coinbase.iter().enumerate() {
+            let outpoint = OutPoint::Coinbase {
+                merkle_root,
+                vout: vout as u32,
+            };
+            record(&self.utxos, wtxn, &mut undo.utxos, &outpoint)?;
+            self.put_utxo(wtxn, &outpoint, output)?;
+        }
+        for (index, transaction) in body.transactions.iter().enumerate() {
             let spent_utxos = self.get_utxos(wtxn, &transaction.inputs)?;
+            if self.index_transactions {
+                let location = TransactionLocation {
+                    height: block_height,
+                    index: index as u32,
+                    spent_utxos: spent_utxos.clone(),
+                };
+                self.txid_to_location
+                    .put(wtxn, &transaction.txid(), &location)?;
+            }
+            // Transferred names keep their values and records, read them
+            // before spent names are removed.
+            let mut transferred = HashMap::<Key, (Value, Option<Vec<Record>>)>::new();
+            for output in &transaction.outputs {
+                if let Content::Custom(BitNamesOutput::Transfer { key }) = &output.content {
+                    let value = self
+                        .key_to_value
+                        .get(wtxn, key)?
+                        .unwrap_or(Value::from([0; 32]));
+                    let records = self.key_to_records.get(wtxn, key)?;
+                    transferred.insert(*key, (value, records));
+                }
+            }
             // Delete spent utxos from utxo set.
             for (input, output) in transaction.inputs.iter().zip(spent_utxos.iter()) {
                 // Update BitNames specific caches.
                 match &output.content {
-                    Content::Custom(BitNamesOutput::KeyValue { key, .. }) => {
+                    Content::Custom(BitNamesOutput::KeyValue { key, .. })
+                    | Content::Custom(BitNamesOutput::Transfer { key })
+                    | Content::Custom(BitNamesOutput::KeyRecords { key, .. }) => {
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
                         self.key_to_value.delete(wtxn, key)?;
+                        record(&self.key_to_records, wtxn, &mut undo.key_to_records, key)?;
+                        self.key_to_records.delete(wtxn, key)?;
+                    }
+                    Content::Custom(BitNamesOutput::Subname { parent, label }) => {
+                        let key = subname_key(parent, label);
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, &key)?;
+                        self.key_to_value.delete(wtxn, &key)?;
+                        record(&self.key_to_records, wtxn, &mut undo.key_to_records, &key)?;
+                        self.key_to_records.delete(wtxn, &key)?;
+                    }
+                    Content::Custom(BitNamesOutput::Bid { key, .. }) => {
+                        if let Some(mut auction) = self.key_to_auction.get(wtxn, key)? {
+                            if auction.bids.contains(input) {
+                                auction.bids.retain(|bid| bid != input);
+                                record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                                self.key_to_auction.put(wtxn, key, &auction)?;
+                            }
+                        }
+                    }
+                    Content::Custom(BitNamesOutput::RevealedBid { key, .. }) => {
+                        // Spending the winning bid spends the name.
+                        let auction = self.key_to_auction.get(wtxn, key)?;
+                        if let Some(Auction {
+                            winner: Some((_, winner)),
+                            ..
+                        }) = auction
+                        {
+                            if winner == *input {
+                                record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
+                                self.key_to_value.delete(wtxn, key)?;
+                                record(&self.key_to_records, wtxn, &mut undo.key_to_records, key)?;
+                                self.key_to_records.delete(wtxn, key)?;
+                                record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                                self.key_to_auction.delete(wtxn, key)?;
+                            }
+                        }
                     }
                     Content::Custom(BitNamesOutput::Commitment(commitment)) => {
+                        record(
+                            &self.commitment_to_key,
+                            wtxn,
+                            &mut undo.commitment_to_key,
+                            commitment,
+                        )?;
                         self.commitment_to_key.delete(wtxn, commitment)?;
                     }
+                    Content::Withdrawal { .. } => {
+                        record(
+                            &self.withdrawal_statuses,
+                            wtxn,
+                            &mut undo.withdrawal_statuses,
+                            input,
+                        )?;
+                        self.withdrawal_statuses.delete(wtxn, input)?;
+                    }
                     _ => {}
                 }
-                self.utxos.delete(wtxn, input)?;
+                record(&self.utxos, wtxn, &mut undo.utxos, input)?;
+                self.delete_utxo(wtxn, input)?;
             }
             let txid = transaction.txid();
             for vout in 0..transaction.outputs.len() {
@@ -493,36 +2002,262 @@ impl BitNamesState {
                 // Update BitNames specific caches.
                 match &output.content {
                     Content::Custom(BitNamesOutput::KeyValue { key, value }) => {
+                        self.push_key_history(
+                            wtxn,
+                            &mut undo,
+                            key,
+                            KeyHistoryEntry {
+                                height: block_height,
+                                outpoint,
+                                address: output.address,
+                                value: Some(*value),
+                            },
+                        )?;
+                        self.renew_key(wtxn, &mut undo, key, block_height)?;
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
+                        self.key_to_value.put(wtxn, key, value)?;
+                    }
+                    Content::Custom(BitNamesOutput::Transfer { key }) => {
+                        let (value, records) = &transferred[key];
+                        self.push_key_history(
+                            wtxn,
+                            &mut undo,
+                            key,
+                            KeyHistoryEntry {
+                                height: block_height,
+                                outpoint,
+                                address: output.address,
+                                value: Some(*value),
+                            },
+                        )?;
+                        self.renew_key(wtxn, &mut undo, key, block_height)?;
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
                         self.key_to_value.put(wtxn, key, value)?;
+                        if let Some(records) = records {
+                            record(&self.key_to_records, wtxn, &mut undo.key_to_records, key)?;
+                            self.key_to_records.put(wtxn, key, records)?;
+                        }
+                    }
+                    Content::Custom(BitNamesOutput::KeyRecords { key, records }) => {
+                        let value = Value::from(hash(records));
+                        self.push_key_history(
+                            wtxn,
+                            &mut undo,
+                            key,
+                            KeyHistoryEntry {
+                                height: block_height,
+                                outpoint,
+                                address: output.address,
+                                value: Some(value),
+                            },
+                        )?;
+                        self.renew_key(wtxn, &mut undo, key, block_height)?;
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
+                        self.key_to_value.put(wtxn, key, &value)?;
+                        record(&self.key_to_records, wtxn, &mut undo.key_to_records, key)?;
+                        self.key_to_records.put(wtxn, key, records)?;
+                    }
+                    Content::Custom(BitNamesOutput::Subname { parent, label }) => {
+                        let key = subname_key(parent, label);
+                        if self.key_to_value.get(wtxn, &key)?.is_some() {
+                            self.remove_name(wtxn, &mut undo, &key)?;
+                        }
+                        let value = Value::from([0; 32]);
+                        self.push_key_history(
+                            wtxn,
+                            &mut undo,
+                            &key,
+                            KeyHistoryEntry {
+                                height: block_height,
+                                outpoint,
+                                address: output.address,
+                                value: Some(value),
+                            },
+                        )?;
+                        self.renew_key(wtxn, &mut undo, &key, block_height)?;
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, &key)?;
+                        self.key_to_value.put(wtxn, &key, &value)?;
+                        record(&self.key_to_parent, wtxn, &mut undo.key_to_parent, &key)?;
+                        self.key_to_parent.put(wtxn, &key, parent)?;
+                        record(
+                            &self.key_to_subnames,
+                            wtxn,
+                            &mut undo.key_to_subnames,
+                            parent,
+                        )?;
+                        let mut subnames =
+                            self.key_to_subnames.get(wtxn, parent)?.unwrap_or_default();
+                        if !subnames.contains(&key) {
+                            subnames.push(key);
+                            self.key_to_subnames.put(wtxn, parent, &subnames)?;
+                        }
+                    }
+                    Content::Custom(BitNamesOutput::Bid { key, .. }) => {
+                        record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                        if let Some(mut auction) = self.key_to_auction.get(wtxn, key)? {
+                            auction.bids.push(outpoint);
+                            self.key_to_auction.put(wtxn, key, &auction)?;
+                        } else {
+                            // The first bid for a name starts an auction.
+                            let auction = Auction {
+                                start_height: block_height,
+                                winner: None,
+                                price: 0,
+                                bids: vec![outpoint],
+                            };
+                            self.key_to_auction.put(wtxn, key, &auction)?;
+                            let end_height = auction_end_height(&auction);
+                            record(
+                                &self.auction_end_height_to_keys,
+                                wtxn,
+                                &mut undo.auction_end_height_to_keys,
+                                &end_height,
+                            )?;
+                            let mut keys = self
+                                .auction_end_height_to_keys
+                                .get(wtxn, &end_height)?
+                                .unwrap_or_default();
+                            keys.push(*key);
+                            self.auction_end_height_to_keys
+                                .put(wtxn, &end_height, &keys)?;
+                        }
+                    }
+                    Content::Custom(BitNamesOutput::RevealedBid { key, amount, .. }) => {
+                        let mut auction = self
+                            .key_to_auction
+                            .get(wtxn, key)?
+                            .ok_or(BitNamesError::AuctionNotFound { key: *key })?;
+                        // Ties go to the bid that was revealed first.
+                        match auction.winner {
+                            Some((highest, _)) if *amount <= highest => {
+                                auction.price = auction.price.max(*amount);
+                            }
+                            Some((highest, _)) => {
+                                auction.price = highest;
+                                auction.winner = Some((*amount, outpoint));
+                            }
+                            None => {
+                                auction.winner = Some((*amount, outpoint));
+                            }
+                        }
+                        record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                        self.key_to_auction.put(wtxn, key, &auction)?;
                     }
                     Content::Custom(BitNamesOutput::Reveal { key, salt }) => {
+                        // Cancel the auction for the name, if there is one.
+                        record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                        self.key_to_auction.delete(wtxn, key)?;
                         let commitment = hmac(key, salt);
+                        record(
+                            &self.key_to_commitment,
+                            wtxn,
+                            &mut undo.key_to_commitment,
+                            key,
+                        )?;
                         self.key_to_commitment.put(wtxn, key, &commitment)?;
+                        record(
+                            &self.commitment_to_key,
+                            wtxn,
+                            &mut undo.commitment_to_key,
+                            &commitment,
+                        )?;
                         self.commitment_to_key.put(wtxn, &commitment, key)?;
+                        record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
                         self.key_to_value.put(wtxn, key, &Value::from([0; 32]))?;
+                        self.push_key_history(
+                            wtxn,
+                            &mut undo,
+                            key,
+                            KeyHistoryEntry {
+                                height: block_height,
+                                outpoint,
+                                address: output.address,
+                                value: Some(Value::from([0; 32])),
+                            },
+                        )?;
+                        self.renew_key(wtxn, &mut undo, key, block_height)?;
                     }
                     Content::Custom(BitNamesOutput::Commitment(commitment)) => {
+                        record(
+                            &self.commitment_to_height,
+                            wtxn,
+                            &mut undo.commitment_to_height,
+                            commitment,
+                        )?;
                         self.commitment_to_height
                             .put(wtxn, commitment, &block_height)?;
+                        record(
+                            &self.commitment_to_outpoint,
+                            wtxn,
+                            &mut undo.commitment_to_outpoint,
+                            commitment,
+                        )?;
                         self.commitment_to_outpoint
                             .put(wtxn, commitment, &outpoint)?;
+                        record(
+                            &self.height_to_commitments,
+                            wtxn,
+                            &mut undo.height_to_commitments,
+                            &block_height,
+                        )?;
+                        let mut commitments = self
+                            .height_to_commitments
+                            .get(wtxn, &block_height)?
+                            .unwrap_or_default();
+                        commitments.push(*commitment);
+                        self.height_to_commitments
+                            .put(wtxn, &block_height, &commitments)?;
+                    }
+                    Content::Withdrawal { .. } => {
+                        self.put_withdrawal_status(
+                            wtxn,
+                            &mut undo,
+                            &outpoint,
+                            &WithdrawalStatus::Pending,
+                        )?;
                     }
                     _ => {}
                 }
                 // Update utxos.
-                self.utxos.put(wtxn, &outpoint, &output)?;
+                record(&self.utxos, wtxn, &mut undo.utxos, &outpoint)?;
+                self.put_utxo(wtxn, &outpoint, &output)?;
             }
         }
-        let mut expired_commitments: Vec<Commitment> = vec![];
-        for item in self.commitment_to_height.iter(wtxn)? {
-            let (commitment, height) = item?;
-            if block_height - height > COMMITMENT_MAX_AGE {
-                expired_commitments.push(commitment);
+        // Only commitments created exactly COMMITMENT_MAX_AGE + 1 blocks ago
+        // expire in this block, older ones were removed already.
+        let expired_height = block_height.checked_sub(COMMITMENT_MAX_AGE + 1);
+        let expired_commitments = match expired_height {
+            Some(expired_height) => {
+                record(
+                    &self.height_to_commitments,
+                    wtxn,
+                    &mut undo.height_to_commitments,
+                    &expired_height,
+                )?;
+                let commitments = self
+                    .height_to_commitments
+                    .get(wtxn, &expired_height)?
+                    .unwrap_or_default();
+                self.height_to_commitments.delete(wtxn, &expired_height)?;
+                commitments
             }
-        }
+            None => vec![],
+        };
         for commitment in &expired_commitments {
             if let Some(key) = self.commitment_to_key.get(wtxn, commitment)? {
+                record(
+                    &self.key_to_commitment,
+                    wtxn,
+                    &mut undo.key_to_commitment,
+                    &key,
+                )?;
                 self.key_to_commitment.delete(wtxn, &key)?;
+                record(
+                    &self.commitment_to_key,
+                    wtxn,
+                    &mut undo.commitment_to_key,
+                    commitment,
+                )?;
                 self.commitment_to_key.delete(wtxn, commitment)?;
             }
             let outpoint = self.commitment_to_outpoint.get(wtxn, commitment)?.ok_or(
@@ -530,14 +2265,272 @@ impl BitNamesState {
                     commitment: *commitment,
                 },
             )?;
-            self.utxos.delete(wtxn, &outpoint)?;
+            record(&self.utxos, wtxn, &mut undo.utxos, &outpoint)?;
+            self.delete_utxo(wtxn, &outpoint)?;
+            record(
+                &self.commitment_to_height,
+                wtxn,
+                &mut undo.commitment_to_height,
+                commitment,
+            )?;
             self.commitment_to_height.delete(wtxn, commitment)?;
+            record(
+                &self.commitment_to_outpoint,
+                wtxn,
+                &mut undo.commitment_to_outpoint,
+                commitment,
+            )?;
             self.commitment_to_outpoint.delete(wtxn, commitment)?;
         }
+        // Award names of auctions that ended in this block.
+        record(
+            &self.auction_end_height_to_keys,
+            wtxn,
+            &mut undo.auction_end_height_to_keys,
+            &block_height,
+        )?;
+        let auctioned_keys = self
+            .auction_end_height_to_keys
+            .get(wtxn, &block_height)?
+            .unwrap_or_default();
+        self.auction_end_height_to_keys
+            .delete(wtxn, &block_height)?;
+        for key in &auctioned_keys {
+            // The auction may have been cancelled, and another one started.
+            let auction = match self.key_to_auction.get(wtxn, key)? {
+                Some(auction) if auction_end_height(&auction) == block_height => auction,
+                _ => continue,
+            };
+            for bid in &auction.bids {
+                record(&self.utxos, wtxn, &mut undo.utxos, bid)?;
+                self.delete_utxo(wtxn, bid)?;
+            }
+            match auction.winner {
+                Some((_, outpoint)) => {
+                    let output = self
+                        .utxos
+                        .get(wtxn, &outpoint)?
+                        .ok_or::<Error>(sdk_types::Error::UtxoDoesNotExist { outpoint }.into())?;
+                    let value = Value::from([0; 32]);
+                    self.push_key_history(
+                        wtxn,
+                        &mut undo,
+                        key,
+                        KeyHistoryEntry {
+                            height: block_height,
+                            outpoint,
+                            address: output.address,
+                            value: Some(value),
+                        },
+                    )?;
+                    self.renew_key(wtxn, &mut undo, key, block_height)?;
+                    record(&self.key_to_value, wtxn, &mut undo.key_to_value, key)?;
+                    self.key_to_value.put(wtxn, key, &value)?;
+                    if !auction.bids.is_empty() {
+                        record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                        let auction = Auction {
+                            bids: vec![],
+                            ..auction
+                        };
+                        self.key_to_auction.put(wtxn, key, &auction)?;
+                    }
+                }
+                // Nobody revealed a bid, so the name is available again.
+                None => {
+                    record(&self.key_to_auction, wtxn, &mut undo.key_to_auction, key)?;
+                    self.key_to_auction.delete(wtxn, key)?;
+                }
+            }
+        }
+
+        // Expire names that were not renewed in time.
+        record(
+            &self.expiry_height_to_keys,
+            wtxn,
+            &mut undo.expiry_height_to_keys,
+            &block_height,
+        )?;
+        let expiring_keys = self
+            .expiry_height_to_keys
+            .get(wtxn, &block_height)?
+            .unwrap_or_default();
+        self.expiry_height_to_keys.delete(wtxn, &block_height)?;
+        for key in &expiring_keys {
+            if self.key_to_expiry_height.get(wtxn, key)? != Some(block_height) {
+                continue;
+            }
+            self.remove_name(wtxn, &mut undo, key)?;
+        }
+
+        let changed_keys: Vec<Key> = undo.key_to_value.keys().copied().collect();
+        for key in &changed_keys {
+            // Names that were spent without a new output, or expired, end
+            // their history with an entry without a value.
+            if self.key_to_value.get(wtxn, key)?.is_none() {
+                let last_entry = self.get_last_key_history_entry(wtxn, key, block_height)?;
+                if let Some(last_entry) = last_entry.filter(|entry| entry.value.is_some()) {
+                    let entry = KeyHistoryEntry {
+                        height: block_height,
+                        value: None,
+                        ..last_entry
+                    };
+                    self.push_key_history(wtxn, &mut undo, key, entry)?;
+                }
+            }
+            self.update_state_tree(wtxn, key)?;
+        }
+        self.disconnect_data.put(wtxn, &block_height, &undo)?;
         Ok(())
     }
 }
 
+/// Version of the database layout, bump it and add a migration to
+/// `MIGRATIONS` whenever the layout changes.
+pub const SCHEMA_VERSION: u32 = 1;
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+type Migration = fn(&BitNamesState, &mut RwTxn) -> Result<(), Error>;
+/// `MIGRATIONS[version]` upgrades databases from `version` to `version + 1`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [BitNamesState::migrate_from_baseline];
+
+const SNAPSHOT_VERSION: u32 = 1;
+/// Databases left out of snapshots, they grow with every block and are either
+/// rebuilt on import or only kept for blocks connected locally.
+const LOCAL_DATABASES: [&str; 3] = ["bodies", "block_hash_to_height", "txid_to_location"];
+
+/// Snapshot file contents, preceded by `SNAPSHOT_VERSION` and followed by a
+/// checksum.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    height: u32,
+    block_hash: BlockHash,
+    databases: Vec<(String, Vec<(Vec<u8>, Vec<u8>)>)>,
+}
+
+/// Database layouts of older schema versions, read by migrations.
+mod v0 {
+    use super::*;
+
+    #[derive(Deserialize)]
+    pub struct Header {
+        pub prev_side_block_hash: BlockHash,
+        pub prev_main_block_hash: bitcoin::BlockHash,
+        pub merkle_root: MerkleRoot,
+    }
+}
+
+/// Status of a withdrawal included in the bundle `txid`.
+fn withdrawal_status(
+    txid: bitcoin::Txid,
+    bundle_status: Option<WithdrawalBundleStatus>,
+) -> WithdrawalStatus {
+    match bundle_status {
+        None => WithdrawalStatus::Bundled { txid },
+        Some(WithdrawalBundleStatus::Failed) => WithdrawalStatus::Refunded { txid },
+        Some(WithdrawalBundleStatus::Confirmed { main_block_height }) => {
+            WithdrawalStatus::Confirmed {
+                txid,
+                main_block_height,
+            }
+        }
+    }
+}
+
+/// Check that `body` fits in `BLOCK_SIZE_LIMIT`, returns its size.
+pub fn validate_body_size(body: &Body) -> Result<usize, Error> {
+    let size = bincode::serialized_size(body)? as usize;
+    if size > BLOCK_SIZE_LIMIT {
+        Err(BitNamesError::BlockTooLarge {
+            size,
+            max_size: BLOCK_SIZE_LIMIT,
+        })?;
+    }
+    Ok(size)
+}
+
+/// Names registered or bid on and commitments created by `transaction`, at
+/// most one transaction in a block can register or bid on a name or create a
+/// commitment.
+pub fn transaction_claims(transaction: &Transaction) -> (Vec<Key>, Vec<Commitment>) {
+    let mut keys = vec![];
+    let mut commitments = vec![];
+    for output in &transaction.outputs {
+        match output.content {
+            Content::Custom(BitNamesOutput::Reveal { key, .. })
+            | Content::Custom(BitNamesOutput::Bid { key, .. }) => keys.push(key),
+            Content::Custom(BitNamesOutput::Subname { parent, label }) => {
+                keys.push(subname_key(&parent, &label))
+            }
+            Content::Custom(BitNamesOutput::Commitment(commitment)) => commitments.push(commitment),
+            _ => {}
+        }
+    }
+    (keys, commitments)
+}
+
+/// Outpoints of a bundle in utxo database order.
+fn sorted_outpoints(spent_utxos: &HashMap<OutPoint, Output>) -> Vec<OutPoint> {
+    let mut outpoints: Vec<OutPoint> = spent_utxos.keys().copied().collect();
+    outpoints.sort_by_cached_key(|outpoint| bincode::serialize(outpoint).unwrap());
+    outpoints
+}
+
+/// Position of the state tree node at `depth` on the path to `path`.
+fn state_tree_position(path: &Hash, depth: usize) -> (u16, Hash) {
+    let mut prefix = Hash::default();
+    prefix[..depth / 8].copy_from_slice(&path[..depth / 8]);
+    if depth % 8 != 0 {
+        prefix[depth / 8] = path[depth / 8] & !(0xff >> (depth % 8));
+    }
+    (depth as u16, prefix)
+}
+
+/// Position of the sibling of the node at `depth + 1` on the path to `path`.
+fn state_tree_sibling(path: &Hash, depth: usize) -> (u16, Hash) {
+    let (position_depth, mut prefix) = state_tree_position(path, depth + 1);
+    prefix[depth / 8] ^= 0x80 >> (depth % 8);
+    (position_depth, prefix)
+}
+
+/// Save the value `key` has in `db` before it is first modified by a block.
+fn record<K, V, KC, DC>(
+    db: &Database<KC, DC>,
+    txn: &RoTxn,
+    prior: &mut HashMap<K, Option<V>>,
+    key: &K,
+) -> Result<(), Error>
+where
+    K: Copy + Eq + std::hash::Hash,
+    KC: for<'a> heed::BytesEncode<'a, EItem = K>,
+    DC: for<'a> heed::BytesDecode<'a, DItem = V>,
+{
+    if !prior.contains_key(key) {
+        let value = db.get(txn, key)?;
+        prior.insert(*key, value);
+    }
+    Ok(())
+}
+
+/// Put back values saved with `record`, deleting entries that did not exist.
+fn restore<K, V, KC, DC>(
+    db: &Database<KC, DC>,
+    wtxn: &mut RwTxn,
+    prior: &HashMap<K, Option<V>>,
+) -> Result<(), Error>
+where
+    KC: for<'a> heed::BytesEncode<'a, EItem = K>,
+    DC: for<'a> heed::BytesEncode<'a, EItem = V>,
+{
+    for (key, value) in prior {
+        match value {
+            Some(value) => db.put(wtxn, key, value)?,
+            None => {
+                db.delete(wtxn, key)?;
+            }
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("authorization error")]
@@ -550,6 +2543,37 @@ pub enum Error {
     Header(#[from] HeaderError),
     #[error("heed error")]
     Heed(#[from] heed::Error),
+    #[error("schema error")]
+    Schema(#[from] SchemaError),
+    #[error("snapshot error")]
+    Snapshot(#[from] SnapshotError),
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("bincode error")]
+    Bincode(#[from] bincode::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SchemaError {
+    #[error(
+        "database schema version {version} is newer than supported version {supported_version}"
+    )]
+    UnsupportedVersion {
+        version: u32,
+        supported_version: u32,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("unsupported snapshot version {version}")]
+    UnsupportedVersion { version: u32 },
+    #[error("invalid snapshot checksum")]
+    InvalidChecksum,
+    #[error("unknown database {name} in snapshot")]
+    UnknownDatabase { name: String },
+    #[error("snapshot best block doesn't match its headers")]
+    InvalidBestBlock,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -558,9 +2582,29 @@ pub enum HeaderError {
     InvalidMerkleRoot,
     #[error("invalid previous side block hash")]
     InvalidPrevSideBlockHash,
+    #[error("invalid state root")]
+    InvalidStateRoot,
 }
 
-const COMMITMENT_MAX_AGE: u32 = 10;
+/// Maximum size of a bincode serialized block body in bytes.
+pub const BLOCK_SIZE_LIMIT: usize = 100 * 1024;
+/// Number of blocks after a commitment in which it can be revealed.
+pub const COMMITMENT_MAX_AGE: u32 = 10;
+/// Maximum number of records of a name.
+pub const MAX_RECORDS: usize = 16;
+/// Maximum length of a txt record in bytes.
+pub const MAX_TXT_RECORD_LENGTH: usize = 255;
+/// Maximum size of the bincode serialized records of a name in bytes.
+pub const MAX_RECORDS_SIZE: usize = 1024;
+/// Number of blocks after the first bid in which bids for a name are accepted.
+pub const AUCTION_BIDDING_PERIOD: u32 = 20;
+/// Number of blocks after bidding closes in which bids can be revealed.
+pub const AUCTION_REVEAL_PERIOD: u32 = 10;
+
+/// Height of the last block in which bids for `auction` can be revealed.
+fn auction_end_height(auction: &Auction) -> u32 {
+    auction.start_height + AUCTION_BIDDING_PERIOD + AUCTION_REVEAL_PERIOD - 1
+}
 #[derive(Debug, thiserror::Error)]
 pub enum BitNamesError {
     #[error("invalid name commitment")]
@@ -588,6 +2632,96 @@ pub enum BitNamesError {
     },
     #[error("invalid key {key}")]
     InvalidKey { key: Key },
+    #[error("name {key} has too many records {records} > {max_records}")]
+    TooManyRecords {
+        key: Key,
+        records: usize,
+        max_records: usize,
+    },
+    #[error("name {key} has a txt record that is too long {length} > {max_length}")]
+    TxtRecordTooLong {
+        key: Key,
+        length: usize,
+        max_length: usize,
+    },
+    #[error("records of name {key} are too large {size} > {max_size}")]
+    RecordsTooLarge {
+        key: Key,
+        size: usize,
+        max_size: usize,
+    },
+    #[error("subname of {parent} created without spending it")]
+    ParentNameNotSpent { parent: Key },
+    #[error("subname {key} of {parent} is already registered")]
+    SubnameAlreadyRegistered { key: Key, parent: Key },
+    #[error("no auction for name {key}")]
+    AuctionNotFound { key: Key },
+    #[error("name {key} is being auctioned")]
+    NameUnderAuction { key: Key },
+    #[error("name {key} is not available")]
+    NameNotAvailable { key: Key },
+    #[error("bidding for name {key} is closed")]
+    BiddingClosed { key: Key },
+    #[error("bids for name {key} can't be revealed now")]
+    NotInRevealPeriod { key: Key },
+    #[error("bid for name {key} spent without revealing it")]
+    BidNotRevealed { key: Key },
+    #[error("revealed bid for name {key} doesn't match a spent bid")]
+    InvalidBidReveal { key: Key },
+    #[error("bid for name {key} exceeds its deposit {amount} > {deposit}")]
+    BidExceedsDeposit { key: Key, amount: u64, deposit: u64 },
+    #[error("bid for name {key} has a deposit that is too low {deposit} < {min_deposit}")]
+    BidDepositTooLow {
+        key: Key,
+        deposit: u64,
+        min_deposit: u64,
+    },
+    #[error("auction for name {key} ends at height {end_height}")]
+    AuctionNotEnded { key: Key, end_height: u32 },
+    #[error("auction price {price} for name {key} is not paid")]
+    AuctionPriceNotPaid { key: Key, price: u64 },
+    #[error("registration fee not paid {fee} < {registration_fee}")]
+    RegistrationFeeNotPaid { fee: u64, registration_fee: u64 },
+    #[error("name {key} is registered or bid on by more than one transaction")]
+    DuplicateNameRegistration { key: Key },
+    #[error("commitment {commitment} is created by more than one transaction")]
+    DuplicateCommitment { commitment: Commitment },
+    #[error("coinbase outputs can only hold value")]
+    InvalidCoinbaseOutput,
+    #[error("coinbase value exceeds collected fees {coinbase_value} > {fees}")]
+    CoinbaseExceedsFees { coinbase_value: u64, fees: u64 },
+    #[error("coinbase value overflows")]
+    CoinbaseValueOverflow,
+    #[error("block fees overflow")]
+    FeesOverflow,
+    #[error("block body too large {size} > {max_size}")]
+    BlockTooLarge { size: usize, max_size: usize },
+    #[error("{outpoint} is spent more than once")]
+    DoubleSpend { outpoint: OutPoint },
+    #[error("name {key} expired at height {expiry_height}")]
+    NameExpired { key: Key, expiry_height: u32 },
     #[error("bundle too heavy {weight} > {max_weight}")]
     BundleTooHeavy { weight: usize, max_weight: usize },
+    #[error("deposit block {deposit_block_hash} was already processed")]
+    DepositBlockAlreadyProcessed {
+        deposit_block_hash: bitcoin::BlockHash,
+    },
+    #[error("deposit block {deposit_block_hash} at height {deposit_block_height} is not after the last deposit block at height {last_deposit_block_height}")]
+    DepositBlockOutOfOrder {
+        deposit_block_hash: bitcoin::BlockHash,
+        deposit_block_height: u32,
+        last_deposit_block_height: u32,
+    },
+    #[error("deposits without a deposit block hash")]
+    MissingDepositBlockHash,
+    #[error("{outpoint} is not a deposit outpoint")]
+    InvalidDepositOutPoint { outpoint: OutPoint },
+    #[error("deposit {outpoint} is not a value output")]
+    InvalidDepositOutput { outpoint: OutPoint },
+    #[error("deposit {outpoint} already exists")]
+    DepositAlreadyExists { outpoint: OutPoint },
+    #[error("status for unknown withdrawal bundle {txid}")]
+    UnknownWithdrawalBundle { txid: bitcoin::Txid },
+    #[error("no disconnect data for block {block_height}")]
+    DisconnectDataNotFound { block_height: u32 },
 }
@@ -0,0 +1,206 @@
+//! Helpers for tests of the state and of crates built on it.
+
+use crate::bitcoin::hashes::Hash as _;
+use crate::sdk_authorization_ed25519_dalek::{get_address, Authorization};
+use crate::*;
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer as _};
+
+/// Open an empty environment in a temporary directory.
+pub fn new_env(name: &str) -> heed::Env {
+    let env_path =
+        std::env::temp_dir().join(format!("bitnames-{}-{}.mdb", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&env_path);
+    std::fs::create_dir_all(&env_path).unwrap();
+    heed::EnvOpenOptions::new()
+        .map_size(100 * 1024 * 1024) // 100MB
+        .max_dbs(BitNamesState::NUM_DBS)
+        .open(env_path)
+        .unwrap()
+}
+
+/// Keypair of the test account `index`.
+pub fn keypair(index: u8) -> Keypair {
+    let secret = SecretKey::from_bytes(&[index; 32]).unwrap();
+    let public = PublicKey::from(&secret);
+    Keypair { secret, public }
+}
+
+pub fn address(index: u8) -> Address {
+    get_address(&keypair(index).public)
+}
+
+/// Mainchain address of the test account `index`.
+pub fn main_address(index: u8) -> bitcoin::Address {
+    bitcoin::Address {
+        payload: bitcoin::util::address::Payload::WitnessProgram {
+            version: bitcoin::util::address::WitnessVersion::V0,
+            program: vec![index; 20],
+        },
+        network: bitcoin::Network::Regtest,
+    }
+}
+
+/// Sign every input of `transaction` with the keypair of account `index`.
+pub fn authorize(index: u8, transaction: Transaction) -> AuthorizedTransaction {
+    let keypair = keypair(index);
+    let message = bincode::serialize(&transaction).unwrap();
+    let authorizations = transaction
+        .inputs
+        .iter()
+        .map(|_| Authorization {
+            public_key: keypair.public,
+            signature: keypair.sign(&message),
+        })
+        .collect();
+    AuthorizedTransaction {
+        transaction,
+        authorizations,
+    }
+}
+
+/// Peg data with `deposits` from a mainchain block unique to `main_height`.
+pub fn peg_data(main_height: u32, deposits: &[(Address, u64)]) -> TwoWayPegData {
+    let mut two_way_peg_data = TwoWayPegData::default();
+    if deposits.is_empty() {
+        return two_way_peg_data;
+    }
+    let deposit_block_hash = bitcoin::BlockHash::from_inner(hash(&main_height));
+    two_way_peg_data.deposit_block = Some((deposit_block_hash, main_height));
+    for (index, (address, value)) in deposits.iter().enumerate() {
+        let outpoint = OutPoint::Deposit(bitcoin::OutPoint {
+            txid: bitcoin::Txid::from_inner(hash(&(main_height, index))),
+            vout: 0,
+        });
+        let output = Output {
+            address: *address,
+            content: Content::Value(*value),
+        };
+        two_way_peg_data.deposits.insert(outpoint, output);
+    }
+    two_way_peg_data
+}
+
+/// Validate and connect a block with `body` on top of the best block.
+pub fn connect_body(
+    env: &heed::Env,
+    state: &BitNamesState,
+    body: Body,
+    two_way_peg_data: &TwoWayPegData,
+) -> Result<Header, Error> {
+    let mut header = {
+        let rtxn = env.read_txn()?;
+        let (_, prev_header) = state.get_best_header(&rtxn)?;
+        let header = Header {
+            prev_side_block_hash: prev_header.block_hash(),
+            prev_main_block_hash: prev_header.prev_main_block_hash,
+            merkle_root: body.compute_merkle_root(),
+            state_root: Hash::default(),
+        };
+        state.validate_block(&rtxn, &header, &body, two_way_peg_data)?;
+        header
+    };
+    header.state_root = state.compute_state_root(env, &body)?;
+    let mut wtxn = env.write_txn()?;
+    state.connect_block(&mut wtxn, &header, &body, two_way_peg_data)?;
+    wtxn.commit()?;
+    Ok(header)
+}
+
+/// Validate and connect a block with `transactions` and no coinbase.
+pub fn connect(
+    env: &heed::Env,
+    state: &BitNamesState,
+    transactions: Vec<AuthorizedTransaction>,
+) -> Result<Header, Error> {
+    let body = Body::new(transactions, vec![]);
+    connect_body(env, state, body, &TwoWayPegData::default())
+}
+
+/// Connect `blocks` empty blocks.
+pub fn connect_empty(env: &heed::Env, state: &BitNamesState, blocks: u32) {
+    for _ in 0..blocks {
+        connect(env, state, vec![]).unwrap();
+    }
+}
+
+/// Connect empty blocks until the best block is at `height`.
+pub fn connect_until(env: &heed::Env, state: &BitNamesState, height: u32) {
+    connect_empty(env, state, height - best_height(env, state));
+}
+
+/// Deposit `value` for account `index` and withdraw it to its mainchain
+/// address in the next block, returns the withdrawal output.
+pub fn withdraw(env: &heed::Env, state: &BitNamesState, index: u8, value: u64) -> OutPoint {
+    let funds = deposit(env, state, address(index), value);
+    let withdrawal = TransactionBuilder::default()
+        .spend(funds)
+        .withdraw(address(index), main_address(index), value, 0)
+        .build();
+    let withdrawal = authorize(index, withdrawal);
+    connect(env, state, vec![withdrawal.clone()]).unwrap();
+    outpoint(&withdrawal, 0)
+}
+
+/// Peg data reporting `status` for the bundle `txid`.
+pub fn bundle_status(txid: bitcoin::Txid, status: WithdrawalBundleStatus) -> TwoWayPegData {
+    let mut two_way_peg_data = TwoWayPegData::default();
+    two_way_peg_data.bundle_statuses.insert(txid, status);
+    two_way_peg_data
+}
+
+/// Connect a block depositing `value` to `address`, returns the deposit.
+pub fn deposit(env: &heed::Env, state: &BitNamesState, address: Address, value: u64) -> OutPoint {
+    let height = best_height(env, state);
+    let two_way_peg_data = peg_data(height + 1, &[(address, value)]);
+    let body = Body::new(vec![], vec![]);
+    connect_body(env, state, body, &two_way_peg_data).unwrap();
+    *two_way_peg_data.deposits.keys().next().unwrap()
+}
+
+pub fn best_height(env: &heed::Env, state: &BitNamesState) -> u32 {
+    let rtxn = env.read_txn().unwrap();
+    let (height, _) = state.get_best_header(&rtxn).unwrap();
+    height
+}
+
+/// Outpoint of output `vout` of `transaction`.
+pub fn outpoint(transaction: &AuthorizedTransaction, vout: u32) -> OutPoint {
+    OutPoint::Regular {
+        txid: transaction.transaction.txid(),
+        vout,
+    }
+}
+
+/// Commit to and reveal `key` for account `owner` in two blocks, paying the
+/// registration fee from `funds` holding `value`. Returns the name output and
+/// the change output.
+pub fn register(
+    env: &heed::Env,
+    state: &BitNamesState,
+    owner: u8,
+    funds: OutPoint,
+    value: u64,
+    key: Key,
+) -> (OutPoint, OutPoint) {
+    let salt = Salt::from(hash(&(key, owner)));
+    let commit = TransactionBuilder::default()
+        .spend(funds)
+        .commit(address(owner), key, salt)
+        .value(address(owner), value)
+        .build();
+    let commit = authorize(owner, commit);
+    connect(env, state, vec![commit.clone()]).unwrap();
+    let reveal = TransactionBuilder::default()
+        .spend(outpoint(&commit, 0))
+        .spend(outpoint(&commit, 1))
+        .reveal(address(owner), key, salt)
+        .value(address(owner), value - state.params.registration_fee)
+        .build();
+    let reveal = authorize(owner, reveal);
+    connect(env, state, vec![reveal.clone()]).unwrap();
+    (outpoint(&reveal, 0), outpoint(&reveal, 1))
+}
+
+pub fn name(name: &str) -> Key {
+    hash(&name).into()
+}
@@ -0,0 +1,102 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+use std::collections::HashSet;
+
+/// Utxos of `addresses` found by scanning the whole utxo set.
+fn scan_utxos(
+    env: &heed::Env,
+    state: &BitNamesState,
+    addresses: &HashSet<Address>,
+) -> HashSet<OutPoint> {
+    let rtxn = env.read_txn().unwrap();
+    state
+        .utxos
+        .iter(&rtxn)
+        .unwrap()
+        .map(Result::unwrap)
+        .filter(|(_, output)| addresses.contains(&output.address))
+        .map(|(outpoint, _)| outpoint)
+        .collect()
+}
+
+fn indexed_utxos(
+    env: &heed::Env,
+    state: &BitNamesState,
+    addresses: &HashSet<Address>,
+) -> HashSet<OutPoint> {
+    let rtxn = env.read_txn().unwrap();
+    let utxos = state.get_utxos_by_addresses(&rtxn, addresses).unwrap();
+    let outpoints: HashSet<OutPoint> = utxos.iter().map(|(outpoint, _)| *outpoint).collect();
+    assert_eq!(outpoints.len(), utxos.len());
+    outpoints
+}
+
+fn assert_index_consistent(env: &heed::Env, state: &BitNamesState) {
+    for index in 0..4 {
+        let addresses = HashSet::from([address(index)]);
+        assert_eq!(
+            indexed_utxos(env, state, &addresses),
+            scan_utxos(env, state, &addresses)
+        );
+    }
+    let addresses: HashSet<Address> = (0..4).map(address).collect();
+    assert_eq!(
+        indexed_utxos(env, state, &addresses),
+        scan_utxos(env, state, &addresses)
+    );
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(
+        state.address_to_outpoints.len(&rtxn).unwrap(),
+        state.utxos.len(&rtxn).unwrap()
+    );
+}
+
+#[test]
+fn index_follows_connected_and_disconnected_blocks() {
+    let env = new_env("address-index");
+    let state = BitNamesState::new(&env).unwrap();
+    let deposits: Vec<(Address, u64)> = (0..3)
+        .flat_map(|index| [(address(index), 1000), (address(index), 2000)])
+        .collect();
+    let two_way_peg_data = peg_data(1, &deposits);
+    connect_body(&env, &state, Body::new(vec![], vec![]), &two_way_peg_data).unwrap();
+    assert_index_consistent(&env, &state);
+
+    // Move every utxo of account 0 to account 3.
+    let spent: Vec<OutPoint> = {
+        let rtxn = env.read_txn().unwrap();
+        let addresses = HashSet::from([address(0)]);
+        state
+            .get_utxos_by_addresses(&rtxn, &addresses)
+            .unwrap()
+            .into_iter()
+            .map(|(outpoint, _)| outpoint)
+            .collect()
+    };
+    assert_eq!(spent.len(), 2);
+    let transaction = spent
+        .iter()
+        .fold(TransactionBuilder::default(), |builder, outpoint| {
+            builder.spend(*outpoint)
+        })
+        .value(address(3), 1000)
+        .value(address(3), 2000)
+        .build();
+    connect(&env, &state, vec![authorize(0, transaction)]).unwrap();
+    assert_index_consistent(&env, &state);
+    let addresses = HashSet::from([address(0)]);
+    assert!(indexed_utxos(&env, &state, &addresses).is_empty());
+
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    assert_index_consistent(&env, &state);
+    assert_eq!(indexed_utxos(&env, &state, &addresses).len(), 2);
+
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    assert_index_consistent(&env, &state);
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.address_to_outpoints.is_empty(&rtxn).unwrap());
+}
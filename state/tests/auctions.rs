@@ -0,0 +1,282 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+const DEPOSIT: u64 = 50_000;
+
+fn salt(bidder: u8) -> Salt {
+    Salt::from(hash(&bidder))
+}
+
+/// Deposit coins for account `bidder` and bid `amount` for `key` with them,
+/// the bid is output 0.
+fn bid(env: &heed::Env, state: &BitNamesState, bidder: u8, key: Key, amount: u64) -> OutPoint {
+    let funds = deposit(env, state, address(bidder), DEPOSIT);
+    let bid = TransactionBuilder::default()
+        .spend(funds)
+        .bid(
+            address(bidder),
+            key,
+            blind_bid(&key, amount, &salt(bidder)),
+            DEPOSIT,
+        )
+        .build();
+    let bid = authorize(bidder, bid);
+    connect(env, state, vec![bid.clone()]).unwrap();
+    outpoint(&bid, 0)
+}
+
+fn reveal_bid(bidder: u8, bid: OutPoint, key: Key, amount: u64) -> AuthorizedTransaction {
+    let reveal = TransactionBuilder::default()
+        .spend(bid)
+        .reveal_bid(address(bidder), key, salt(bidder), amount, DEPOSIT)
+        .build();
+    authorize(bidder, reveal)
+}
+
+fn auction(env: &heed::Env, state: &BitNamesState, key: &Key) -> Option<Auction> {
+    let rtxn = env.read_txn().unwrap();
+    state.get_auction(&rtxn, key).unwrap()
+}
+
+#[test]
+fn bids_are_revealed_after_bidding_closes() {
+    let env = new_env("auction-reveal-timing");
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    let first = bid(&env, &state, 0, key, 30_000);
+    let start_height = best_height(&env, &state);
+    assert_eq!(auction(&env, &state, &key).unwrap().bids, vec![first]);
+
+    // Bids can't be revealed while bidding is open, or spent without
+    // revealing them.
+    let reveal = reveal_bid(0, first, key, 30_000);
+    let spend = authorize(
+        0,
+        TransactionBuilder::default()
+            .spend(first)
+            .value(address(0), DEPOSIT)
+            .build(),
+    );
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &reveal.transaction),
+            Err(Error::BitNames(BitNamesError::NotInRevealPeriod { .. }))
+        ));
+    }
+
+    // More bids can be placed until bidding closes, `bid` connects two
+    // blocks.
+    connect_until(&env, &state, start_height + AUCTION_BIDDING_PERIOD - 3);
+    let second = bid(&env, &state, 1, key, 20_000);
+    assert_eq!(
+        auction(&env, &state, &key).unwrap().bids,
+        vec![first, second]
+    );
+    let funds = deposit(&env, &state, address(2), DEPOSIT);
+    let late_bid = TransactionBuilder::default()
+        .spend(funds)
+        .bid(address(2), key, blind_bid(&key, 1, &salt(2)), DEPOSIT)
+        .build();
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &late_bid),
+            Err(Error::BitNames(BitNamesError::BiddingClosed { .. }))
+        ));
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &spend.transaction),
+            Err(Error::BitNames(BitNamesError::BidNotRevealed { .. }))
+        ));
+    }
+    connect(&env, &state, vec![reveal]).unwrap();
+    assert_eq!(auction(&env, &state, &key).unwrap().bids, vec![second]);
+
+    // Bids can't be revealed once the auction ended.
+    let end_height = start_height + AUCTION_BIDDING_PERIOD + AUCTION_REVEAL_PERIOD - 1;
+    connect_until(&env, &state, end_height);
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.get_utxo(&rtxn, &second).unwrap().is_none());
+    let reveal = reveal_bid(1, second, key, 20_000);
+    assert!(state
+        .validate_transaction(&rtxn, &reveal.transaction)
+        .is_err());
+}
+
+#[test]
+fn highest_bid_wins_and_pays_the_second_price() {
+    let env = new_env("auction-second-price");
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    let low = bid(&env, &state, 0, key, 20_000);
+    let start_height = best_height(&env, &state);
+    let high = bid(&env, &state, 1, key, 30_000);
+    let _ = bid(&env, &state, 2, key, 40_000);
+    connect_until(&env, &state, start_height + AUCTION_BIDDING_PERIOD - 1);
+    let low_reveal = reveal_bid(0, low, key, 20_000);
+    let high_reveal = reveal_bid(1, high, key, 30_000);
+    connect(&env, &state, vec![low_reveal.clone(), high_reveal.clone()]).unwrap();
+    let auction = auction(&env, &state, &key).unwrap();
+    assert_eq!(auction.winner, Some((30_000, outpoint(&high_reveal, 0))));
+    assert_eq!(auction.price, 20_000);
+
+    // Revealed bids are locked until the auction ends.
+    let refund = TransactionBuilder::default()
+        .spend(outpoint(&low_reveal, 0))
+        .value(address(0), DEPOSIT)
+        .build();
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &refund),
+            Err(Error::BitNames(BitNamesError::AuctionNotEnded { .. }))
+        ));
+    }
+    let end_height = start_height + AUCTION_BIDDING_PERIOD + AUCTION_REVEAL_PERIOD - 1;
+    connect_until(&env, &state, end_height);
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(
+            state.get_value(&rtxn, &key).unwrap(),
+            Some(Value::from([0; 32]))
+        );
+    }
+
+    // The loser gets the deposit back, the winner pays the second price.
+    let unpaid = TransactionBuilder::default()
+        .spend(outpoint(&high_reveal, 0))
+        .transfer(key, address(1))
+        .value(address(1), DEPOSIT - 10_000)
+        .build();
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &unpaid),
+            Err(Error::BitNames(BitNamesError::AuctionPriceNotPaid {
+                price: 20_000,
+                ..
+            }))
+        ));
+    }
+    let claim = TransactionBuilder::default()
+        .spend(outpoint(&high_reveal, 0))
+        .transfer(key, address(1))
+        .value(address(1), DEPOSIT - 20_000)
+        .build();
+    let claim = authorize(1, claim);
+    connect(&env, &state, vec![authorize(0, refund), claim.clone()]).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    let output = state.get_utxo(&rtxn, &outpoint(&claim, 0)).unwrap();
+    assert_eq!(output.unwrap().address, address(1));
+    assert!(state.get_auction(&rtxn, &key).unwrap().is_none());
+}
+
+#[test]
+fn unrevealed_bids_are_forfeited() {
+    let env = new_env("auction-forfeit");
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    let stale = bid(&env, &state, 0, key, 30_000);
+    let start_height = best_height(&env, &state);
+
+    // Nobody reveals a bid, so the name is available again.
+    let end_height = start_height + AUCTION_BIDDING_PERIOD + AUCTION_REVEAL_PERIOD - 1;
+    connect_until(&env, &state, end_height);
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(state.get_utxo(&rtxn, &stale).unwrap().is_none());
+        assert!(state.get_auction(&rtxn, &key).unwrap().is_none());
+        assert_eq!(state.get_value(&rtxn, &key).unwrap(), None);
+    }
+
+    // Disconnecting the last block of the auction restores the bid.
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.get_utxo(&rtxn, &stale).unwrap().is_some());
+    assert_eq!(
+        state.get_auction(&rtxn, &key).unwrap().unwrap().bids,
+        vec![stale]
+    );
+}
+
+#[test]
+fn bids_need_a_minimum_deposit() {
+    let env = new_env("auction-min-deposit");
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    let deposit = state.params.registration_fee - 1;
+    let funds = test_utils::deposit(&env, &state, address(0), deposit);
+    let bid = TransactionBuilder::default()
+        .spend(funds)
+        .bid(address(0), key, blind_bid(&key, 1, &salt(0)), deposit)
+        .build();
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_transaction(&rtxn, &bid),
+        Err(Error::BitNames(BitNamesError::BidDepositTooLow { .. }))
+    ));
+}
+
+#[test]
+fn earlier_commitments_cancel_auctions() {
+    let env = new_env("auction-front-running");
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let commit_salt = Salt::from(hash(&"alice"));
+    let commit = TransactionBuilder::default()
+        .spend(funds)
+        .commit(address(0), key, commit_salt)
+        .value(address(0), 50_000)
+        .build();
+    let commit = authorize(0, commit);
+    connect(&env, &state, vec![commit.clone()]).unwrap();
+
+    // A bid placed after the commitment doesn't block the reveal.
+    let front_run = bid(&env, &state, 1, key, 30_000);
+    let reveal = TransactionBuilder::default()
+        .spend(outpoint(&commit, 0))
+        .spend(outpoint(&commit, 1))
+        .reveal(address(0), key, commit_salt)
+        .value(address(0), 50_000 - state.params.registration_fee)
+        .build();
+    connect(&env, &state, vec![authorize(0, reveal)]).unwrap();
+    assert!(auction(&env, &state, &key).is_none());
+
+    // Bids of cancelled auctions can be spent freely.
+    let refund = TransactionBuilder::default()
+        .spend(front_run)
+        .value(address(1), DEPOSIT)
+        .build();
+    connect(&env, &state, vec![authorize(1, refund)]).unwrap();
+}
+
+#[test]
+fn later_commitments_cant_be_revealed_during_auctions() {
+    let env = new_env("auction-late-commitment");
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    bid(&env, &state, 1, key, 30_000);
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let commit_salt = Salt::from(hash(&"alice"));
+    let commit = TransactionBuilder::default()
+        .spend(funds)
+        .commit(address(0), key, commit_salt)
+        .value(address(0), 50_000)
+        .build();
+    let commit = authorize(0, commit);
+    connect(&env, &state, vec![commit.clone()]).unwrap();
+    let reveal = TransactionBuilder::default()
+        .spend(outpoint(&commit, 0))
+        .spend(outpoint(&commit, 1))
+        .reveal(address(0), key, commit_salt)
+        .value(address(0), 50_000 - state.params.registration_fee)
+        .build();
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_transaction(&rtxn, &reveal),
+        Err(Error::BitNames(BitNamesError::NameUnderAuction { .. }))
+    ));
+}
@@ -0,0 +1,81 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+fn commitment_exists(env: &heed::Env, state: &BitNamesState, commitment: &Commitment) -> bool {
+    let rtxn = env.read_txn().unwrap();
+    let height = state.commitment_to_height.get(&rtxn, commitment).unwrap();
+    let outpoint = state.commitment_to_outpoint.get(&rtxn, commitment).unwrap();
+    assert_eq!(height.is_some(), outpoint.is_some());
+    if let Some(height) = height {
+        let commitments = state.height_to_commitments.get(&rtxn, &height).unwrap();
+        assert!(commitments.unwrap().contains(commitment));
+        assert!(state.get_utxo(&rtxn, &outpoint.unwrap()).unwrap().is_some());
+    }
+    height.is_some()
+}
+
+#[test]
+fn commitments_expire_after_max_age() {
+    let env = new_env("commitment-expiry");
+    let state = BitNamesState::new(&env).unwrap();
+    let first = deposit(&env, &state, address(0), 1000);
+    let second = deposit(&env, &state, address(0), 1000);
+    let salt = Salt::from(hash(&"salt"));
+    let first_commitment = hmac(&name("alice"), &salt);
+    let second_commitment = hmac(&name("bob"), &salt);
+    let commit = |funds, key| {
+        let transaction = TransactionBuilder::default()
+            .spend(funds)
+            .commit(address(0), key, salt)
+            .build();
+        authorize(0, transaction)
+    };
+    connect(&env, &state, vec![commit(first, name("alice"))]).unwrap();
+    let first_height = best_height(&env, &state);
+    connect(&env, &state, vec![commit(second, name("bob"))]).unwrap();
+
+    // Commitments can be revealed in the COMMITMENT_MAX_AGE blocks after
+    // they were created, and are removed in the block after that.
+    connect_empty(&env, &state, COMMITMENT_MAX_AGE - 1);
+    assert!(commitment_exists(&env, &state, &first_commitment));
+    connect_empty(&env, &state, 1);
+    assert_eq!(best_height(&env, &state), first_height + COMMITMENT_MAX_AGE);
+    assert!(commitment_exists(&env, &state, &first_commitment));
+    connect_empty(&env, &state, 1);
+    assert!(!commitment_exists(&env, &state, &first_commitment));
+    assert!(commitment_exists(&env, &state, &second_commitment));
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(state
+            .height_to_commitments
+            .get(&rtxn, &first_height)
+            .unwrap()
+            .is_none());
+    }
+    connect_empty(&env, &state, 1);
+    assert!(!commitment_exists(&env, &state, &second_commitment));
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.height_to_commitments.is_empty(&rtxn).unwrap());
+    assert!(state.commitment_to_height.is_empty(&rtxn).unwrap());
+    drop(rtxn);
+
+    // Disconnecting the block that removed a commitment restores it.
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    assert!(commitment_exists(&env, &state, &second_commitment));
+    assert!(!commitment_exists(&env, &state, &first_commitment));
+}
+
+#[test]
+fn revealed_commitments_are_removed_from_the_index_on_expiry() {
+    let env = new_env("commitment-expiry-revealed");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    register(&env, &state, 0, funds, 50_000, name("alice"));
+    connect_empty(&env, &state, COMMITMENT_MAX_AGE);
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.height_to_commitments.is_empty(&rtxn).unwrap());
+    assert!(state.commitment_to_height.is_empty(&rtxn).unwrap());
+    assert!(state.commitment_to_outpoint.is_empty(&rtxn).unwrap());
+}
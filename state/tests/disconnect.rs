@@ -0,0 +1,189 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+use std::collections::HashSet;
+
+type Dump = Vec<(&'static str, Vec<(Vec<u8>, Vec<u8>)>)>;
+
+/// Raw contents of every database.
+fn dump(env: &heed::Env, state: &BitNamesState) -> Dump {
+    let rtxn = env.read_txn().unwrap();
+    state
+        .raw_databases()
+        .into_iter()
+        .map(|(name, database)| {
+            let entries = database
+                .iter(&rtxn)
+                .unwrap()
+                .map(|item| {
+                    let (key, value) = item.unwrap();
+                    (key.to_vec(), value.to_vec())
+                })
+                .collect();
+            (name, entries)
+        })
+        .collect()
+}
+
+/// Add the databases that differ from `before` to `touched`.
+fn mark_touched(
+    env: &heed::Env,
+    state: &BitNamesState,
+    before: &Dump,
+    touched: &mut HashSet<&'static str>,
+) {
+    for ((name, before), (_, after)) in before.iter().zip(dump(env, state)) {
+        if *before != after {
+            touched.insert(name);
+        }
+    }
+}
+
+#[test]
+fn disconnecting_blocks_restores_every_database() {
+    let env = new_env("disconnect-round-trip");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.index_transactions = true;
+    state.params.name_max_age = 40;
+    let deposits = [
+        (address(0), 1_000_000),
+        (address(0), 100_000),
+        (address(0), 200_000),
+    ];
+    let two_way_peg_data = peg_data(1, &deposits);
+    connect_body(&env, &state, Body::new(vec![], vec![]), &two_way_peg_data).unwrap();
+    let funds = |value| {
+        two_way_peg_data
+            .deposits
+            .iter()
+            .find(|(_, output)| matches!(output.content, Content::Value(v) if v == value))
+            .map(|(outpoint, _)| *outpoint)
+            .unwrap()
+    };
+    let before = dump(&env, &state);
+    let mut touched = HashSet::new();
+
+    // Commit to "alice", to a name that is never revealed and bid for "carol".
+    let alice = name("alice");
+    let alice_salt = Salt::from(hash(&"alice"));
+    let commit = TransactionBuilder::default()
+        .spend(funds(1_000_000))
+        .commit(address(0), alice, alice_salt)
+        .value(address(0), 1_000_000)
+        .build();
+    let commit = authorize(0, commit);
+    let stale = TransactionBuilder::default()
+        .spend(funds(100_000))
+        .commit(address(0), name("stale"), Salt::from(hash(&"stale")))
+        .value(address(0), 100_000)
+        .build();
+    let carol = name("carol");
+    let bid_salt = Salt::from(hash(&"carol"));
+    let bid = TransactionBuilder::default()
+        .spend(funds(200_000))
+        .bid(
+            address(0),
+            carol,
+            blind_bid(&carol, 30_000, &bid_salt),
+            40_000,
+        )
+        .value(address(0), 160_000)
+        .build();
+    let bid = authorize(0, bid);
+    connect(
+        &env,
+        &state,
+        vec![commit.clone(), authorize(0, stale), bid.clone()],
+    )
+    .unwrap();
+    mark_touched(&env, &state, &before, &mut touched);
+    let auction_start = best_height(&env, &state);
+
+    let reveal = TransactionBuilder::default()
+        .spend(outpoint(&commit, 0))
+        .spend(outpoint(&commit, 1))
+        .reveal(address(0), alice, alice_salt)
+        .value(address(0), 1_000_000 - state.params.registration_fee)
+        .build();
+    let reveal = authorize(0, reveal);
+    connect(&env, &state, vec![reveal.clone()]).unwrap();
+    mark_touched(&env, &state, &before, &mut touched);
+
+    let records = TransactionBuilder::default()
+        .spend(outpoint(&reveal, 0))
+        .set_records(address(0), alice, vec![Record::Txt("alice".into())])
+        .build();
+    let records = authorize(0, records);
+    let withdrawal = TransactionBuilder::default()
+        .spend(outpoint(&reveal, 1))
+        .withdraw(address(0), main_address(0), 100_000, 1_000)
+        .value(address(0), 800_000)
+        .build();
+    connect(
+        &env,
+        &state,
+        vec![records.clone(), authorize(0, withdrawal)],
+    )
+    .unwrap();
+    mark_touched(&env, &state, &before, &mut touched);
+
+    let subname = TransactionBuilder::default()
+        .spend(outpoint(&records, 0))
+        .transfer(alice, address(1))
+        .subname(address(0), alice, name("www"))
+        .build();
+    connect(&env, &state, vec![authorize(0, subname)]).unwrap();
+    mark_touched(&env, &state, &before, &mut touched);
+
+    // Reveal the bid once bidding closed, the auction ends and "carol" is
+    // awarded after the reveal period.
+    connect_empty(
+        &env,
+        &state,
+        auction_start + AUCTION_BIDDING_PERIOD - best_height(&env, &state) - 1,
+    );
+    let reveal_bid = TransactionBuilder::default()
+        .spend(outpoint(&bid, 0))
+        .reveal_bid(address(0), carol, bid_salt, 30_000, 40_000)
+        .build();
+    connect(&env, &state, vec![authorize(0, reveal_bid)]).unwrap();
+    mark_touched(&env, &state, &before, &mut touched);
+
+    // Names expire and the withdrawal is bundled.
+    connect_empty(&env, &state, 101 - best_height(&env, &state));
+    mark_touched(&env, &state, &before, &mut touched);
+    let txid = {
+        let rtxn = env.read_txn().unwrap();
+        assert!(state.get_value(&rtxn, &alice).unwrap().is_none());
+        assert!(state.get_value(&rtxn, &carol).unwrap().is_none());
+        state
+            .get_pending_withdrawal_bundle(&rtxn)
+            .unwrap()
+            .unwrap()
+            .transaction
+            .txid()
+    };
+
+    // The bundle fails, and more coins are deposited.
+    let height = best_height(&env, &state);
+    let mut two_way_peg_data = peg_data(height + 1, &[(address(2), 5_000)]);
+    two_way_peg_data
+        .bundle_statuses
+        .insert(txid, WithdrawalBundleStatus::Failed);
+    connect_body(&env, &state, Body::new(vec![], vec![]), &two_way_peg_data).unwrap();
+
+    mark_touched(&env, &state, &before, &mut touched);
+    // Only the schema version is never written by blocks.
+    for (name, _) in &before {
+        assert!(
+            *name == "metadata" || touched.contains(name),
+            "{name} was not touched"
+        );
+    }
+
+    while best_height(&env, &state) > 1 {
+        let mut wtxn = env.write_txn().unwrap();
+        state.disconnect_block(&mut wtxn).unwrap();
+        wtxn.commit().unwrap();
+    }
+    assert_eq!(dump(&env, &state), before);
+}
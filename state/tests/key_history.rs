@@ -0,0 +1,160 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+fn value_at(env: &heed::Env, state: &BitNamesState, key: &Key, height: u32) -> Option<Value> {
+    let rtxn = env.read_txn().unwrap();
+    state.get_value_at(&rtxn, key, height).unwrap()
+}
+
+fn history(env: &heed::Env, state: &BitNamesState, key: &Key) -> Vec<KeyHistoryEntry> {
+    let rtxn = env.read_txn().unwrap();
+    state.get_key_history(&rtxn, key, 0, usize::MAX).unwrap()
+}
+
+#[test]
+fn value_at_follows_updates_and_removals() {
+    let env = new_env("key-history-removal");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    let reveal_height = best_height(&env, &state);
+
+    let value = Value::from(hash(&"value"));
+    let update = TransactionBuilder::default()
+        .spend(name_outpoint)
+        .set(address(1), key, value)
+        .build();
+    let update = authorize(0, update);
+    connect(&env, &state, vec![update.clone()]).unwrap();
+    let update_height = best_height(&env, &state);
+
+    // Spend the name without creating a new name output.
+    let burn = TransactionBuilder::default()
+        .spend(outpoint(&update, 0))
+        .build();
+    connect(&env, &state, vec![authorize(1, burn)]).unwrap();
+    let burn_height = best_height(&env, &state);
+
+    assert_eq!(value_at(&env, &state, &key, reveal_height - 1), None);
+    assert_eq!(
+        value_at(&env, &state, &key, reveal_height),
+        Some(Value::from([0; 32]))
+    );
+    assert_eq!(value_at(&env, &state, &key, update_height), Some(value));
+    assert_eq!(value_at(&env, &state, &key, burn_height), None);
+    let entries = history(&env, &state, &key);
+    assert_eq!(entries.len(), 3);
+    let removal = &entries[2];
+    assert_eq!(removal.height, burn_height);
+    assert_eq!(removal.outpoint, outpoint(&update, 0));
+    assert_eq!(removal.address, address(1));
+    assert_eq!(removal.value, None);
+
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    assert_eq!(value_at(&env, &state, &key, burn_height), Some(value));
+    assert_eq!(history(&env, &state, &key).len(), 2);
+}
+
+#[test]
+fn expired_names_end_their_history() {
+    let env = new_env("key-history-expiry");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.params.name_max_age = 5;
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    register(&env, &state, 0, funds, 50_000, key);
+    let reveal_height = best_height(&env, &state);
+    connect_empty(&env, &state, state.params.name_max_age);
+
+    let expiry_height = reveal_height + state.params.name_max_age;
+    assert!(value_at(&env, &state, &key, expiry_height - 1).is_some());
+    assert_eq!(value_at(&env, &state, &key, expiry_height), None);
+    let entries = history(&env, &state, &key);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].height, expiry_height);
+    assert_eq!(entries[1].value, None);
+}
+
+#[test]
+fn history_records_owners_and_pages_by_block() {
+    let env = new_env("key-history-pages");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    let reveal_height = best_height(&env, &state);
+
+    // Two updates of the name in the same block.
+    let first = TransactionBuilder::default()
+        .spend(name_outpoint)
+        .set(address(1), key, Value::from(hash(&1)))
+        .build();
+    let first = authorize(0, first);
+    let second = TransactionBuilder::default()
+        .spend(outpoint(&first, 0))
+        .set(address(2), key, Value::from(hash(&2)))
+        .build();
+    let second = authorize(1, second);
+    connect(&env, &state, vec![first.clone(), second.clone()]).unwrap();
+    let update_height = best_height(&env, &state);
+    let third = TransactionBuilder::default()
+        .spend(outpoint(&second, 0))
+        .transfer(key, address(3))
+        .build();
+    let third = authorize(2, third);
+    connect(&env, &state, vec![third.clone()]).unwrap();
+
+    let entries = history(&env, &state, &key);
+    let owners: Vec<(u32, OutPoint, Address, Option<Value>)> = entries
+        .iter()
+        .map(|entry| (entry.height, entry.outpoint, entry.address, entry.value))
+        .collect();
+    assert_eq!(
+        owners,
+        vec![
+            (
+                reveal_height,
+                name_outpoint,
+                address(0),
+                Some(Value::from([0; 32]))
+            ),
+            (
+                update_height,
+                outpoint(&first, 0),
+                address(1),
+                Some(Value::from(hash(&1)))
+            ),
+            (
+                update_height,
+                outpoint(&second, 0),
+                address(2),
+                Some(Value::from(hash(&2)))
+            ),
+            (
+                update_height + 1,
+                outpoint(&third, 0),
+                address(3),
+                Some(Value::from(hash(&2)))
+            ),
+        ]
+    );
+
+    // Pages never split the entries of a block.
+    let rtxn = env.read_txn().unwrap();
+    let page = state.get_key_history(&rtxn, &key, 0, 2).unwrap();
+    assert_eq!(page.len(), 3);
+    let next_height = page.last().unwrap().height + 1;
+    let page = state.get_key_history(&rtxn, &key, next_height, 2).unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].height, update_height + 1);
+    assert!(state
+        .get_key_history(&rtxn, &key, update_height + 2, 2)
+        .unwrap()
+        .is_empty());
+    // Requests that leave the limit unset get every entry.
+    let page = state.get_key_history(&rtxn, &key, 0, 0).unwrap();
+    assert_eq!(page.len(), entries.len());
+}
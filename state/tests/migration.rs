@@ -0,0 +1,210 @@
+use bitnames_state::bitcoin::hashes::Hash as _;
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+use heed::types::*;
+use serde::Serialize;
+
+/// Header layout before headers committed to the state.
+#[derive(Serialize)]
+struct BaselineHeader {
+    prev_side_block_hash: BlockHash,
+    prev_main_block_hash: bitcoin::BlockHash,
+    merkle_root: MerkleRoot,
+}
+
+/// Withdrawal output of account 0.
+fn withdrawal_output() -> Output {
+    Output {
+        address: address(0),
+        content: Content::Withdrawal {
+            value: 1000,
+            main_fee: 0,
+            main_address: main_address(0),
+        },
+    }
+}
+
+/// Withdrawal bundle paying out `outpoint`.
+fn baseline_bundle(outpoint: OutPoint) -> WithdrawalBundle {
+    WithdrawalBundle {
+        spent_utxos: [(outpoint, withdrawal_output())].into_iter().collect(),
+        transaction: bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![],
+            output: vec![],
+        },
+    }
+}
+
+/// Write a chain of two blocks in the baseline layout, where "alice" was
+/// registered, a commitment was created and a withdrawal was made in block 1,
+/// and a bundle with an earlier withdrawal is pending.
+fn write_baseline(
+    env: &heed::Env,
+    name_outpoint: OutPoint,
+    commitment: Commitment,
+    withdrawal: OutPoint,
+    bundle: &WithdrawalBundle,
+) {
+    let headers = env
+        .create_database::<OwnedType<u32>, SerdeBincode<BaselineHeader>>(Some("headers"))
+        .unwrap();
+    let key_to_value = env
+        .create_database::<SerdeBincode<Key>, SerdeBincode<Value>>(Some("key_to_value"))
+        .unwrap();
+    let commitment_to_height = env
+        .create_database::<SerdeBincode<Commitment>, OwnedType<u32>>(Some("commitment_to_height"))
+        .unwrap();
+    let utxos = env
+        .create_database::<SerdeBincode<OutPoint>, SerdeBincode<Output>>(Some("utxos"))
+        .unwrap();
+    let last_deposit_block = env
+        .create_database::<OwnedType<u32>, SerdeBincode<bitcoin::BlockHash>>(Some(
+            "last_deposit_block",
+        ))
+        .unwrap();
+    let last_withdrawal_bundle = env
+        .create_database::<OwnedType<u32>, SerdeBincode<WithdrawalBundle>>(Some(
+            "last_withdrawal_bundle",
+        ))
+        .unwrap();
+
+    let mut wtxn = env.write_txn().unwrap();
+    let genesis = BaselineHeader {
+        prev_side_block_hash: BlockHash::default(),
+        prev_main_block_hash: bitcoin::BlockHash::from_inner([0; 32]),
+        merkle_root: Default::default(),
+    };
+    let header = BaselineHeader {
+        prev_side_block_hash: hash(&genesis).into(),
+        prev_main_block_hash: bitcoin::BlockHash::from_inner([1; 32]),
+        merkle_root: Default::default(),
+    };
+    headers.put(&mut wtxn, &0, &genesis).unwrap();
+    headers.put(&mut wtxn, &1, &header).unwrap();
+    let key = name("alice");
+    key_to_value
+        .put(&mut wtxn, &key, &Value::from([0; 32]))
+        .unwrap();
+    let reveal = Output {
+        address: address(0),
+        content: Content::Custom(BitNamesOutput::Reveal {
+            salt: Salt::from(hash(&"salt")),
+            key,
+        }),
+    };
+    utxos.put(&mut wtxn, &name_outpoint, &reveal).unwrap();
+    utxos
+        .put(&mut wtxn, &withdrawal, &withdrawal_output())
+        .unwrap();
+    last_withdrawal_bundle.put(&mut wtxn, &0, bundle).unwrap();
+    commitment_to_height
+        .put(&mut wtxn, &commitment, &1)
+        .unwrap();
+    last_deposit_block
+        .put(&mut wtxn, &0, &bitcoin::BlockHash::from_inner([1; 32]))
+        .unwrap();
+    wtxn.commit().unwrap();
+}
+
+#[test]
+fn baseline_databases_are_migrated() {
+    let env = new_env("migration-baseline");
+    let name_outpoint = OutPoint::Deposit(bitcoin::OutPoint::default());
+    let commitment = hmac(&name("bob"), &Salt::from(hash(&"salt")));
+    let withdrawal = OutPoint::Regular {
+        txid: hash(&"withdrawal").into(),
+        vout: 0,
+    };
+    let bundled_withdrawal = OutPoint::Regular {
+        txid: hash(&"bundled withdrawal").into(),
+        vout: 0,
+    };
+    let bundle = baseline_bundle(bundled_withdrawal);
+    write_baseline(&env, name_outpoint, commitment, withdrawal, &bundle);
+
+    let state = BitNamesState::new(&env).unwrap();
+    let key = name("alice");
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(
+            state.metadata.get(&rtxn, "schema_version").unwrap(),
+            Some(SCHEMA_VERSION)
+        );
+
+        // Headers are relinked after their hashes changed.
+        let (height, header) = state.get_best_header(&rtxn).unwrap();
+        assert_eq!(height, 1);
+        assert_eq!(header.prev_side_block_hash, Header::genesis().block_hash());
+        assert_eq!(header.state_root, Hash::default());
+        assert_eq!(
+            state
+                .block_hash_to_height
+                .get(&rtxn, &header.block_hash())
+                .unwrap(),
+            Some(1)
+        );
+        assert_eq!(
+            state.last_deposit_block.get(&rtxn, &0).unwrap(),
+            Some((bitcoin::BlockHash::from_inner([1; 32]), 0))
+        );
+
+        // Derived indexes are rebuilt.
+        assert_eq!(
+            state.height_to_commitments.get(&rtxn, &1).unwrap(),
+            Some(vec![commitment])
+        );
+        let expiry_height = 1 + state.params.name_max_age;
+        assert_eq!(
+            state.key_to_expiry_height.get(&rtxn, &key).unwrap(),
+            Some(expiry_height)
+        );
+        assert_eq!(
+            state
+                .expiry_height_to_keys
+                .get(&rtxn, &expiry_height)
+                .unwrap(),
+            Some(vec![key])
+        );
+        let history = state.get_key_history(&rtxn, &key, 0, 10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].height, 1);
+        assert_eq!(history[0].outpoint, name_outpoint);
+        assert_eq!(history[0].value, Some(Value::from([0; 32])));
+        assert!(state
+            .address_to_outpoints
+            .get(&rtxn, &(address(0), name_outpoint))
+            .unwrap()
+            .is_some());
+        let proof = state.get_name_proof(&rtxn, &key).unwrap();
+        assert!(verify_name_proof(
+            &state.get_state_root(&rtxn).unwrap(),
+            &proof
+        ));
+
+        // The pending bundle is added to the bundle history.
+        let txid = bundle.transaction.txid();
+        let bundles = state.get_withdrawal_bundles(&rtxn).unwrap();
+        assert_eq!(bundles.len(), 1);
+        let (bundle_txid, info) = &bundles[0];
+        assert_eq!(*bundle_txid, txid);
+        assert!(info.status.is_none());
+        assert_eq!(info.creation_height, 1);
+        assert_eq!(info.outpoints, vec![bundled_withdrawal]);
+        assert!(matches!(
+            state
+                .get_withdrawal_status(&rtxn, &bundled_withdrawal)
+                .unwrap(),
+            Some(WithdrawalStatus::Bundled { txid: bundle_txid }) if bundle_txid == txid
+        ));
+        assert!(matches!(
+            state.get_withdrawal_status(&rtxn, &withdrawal).unwrap(),
+            Some(WithdrawalStatus::Pending)
+        ));
+    }
+
+    // Blocks can be connected on top of the migrated chain.
+    connect_empty(&env, &state, 1);
+    assert_eq!(best_height(&env, &state), 2);
+}
@@ -0,0 +1,95 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+#[test]
+fn names_expire_unless_renewed() {
+    let env = new_env("name-expiry");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.params.name_max_age = 10;
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    let reveal_height = best_height(&env, &state);
+
+    // Renew in the last block before the name expires.
+    connect_empty(&env, &state, state.params.name_max_age - 2);
+    let renew = TransactionBuilder::default()
+        .spend(name_outpoint)
+        .transfer(key, address(0))
+        .build();
+    let renew = authorize(0, renew);
+    connect(&env, &state, vec![renew.clone()]).unwrap();
+    let renew_height = best_height(&env, &state);
+    assert_eq!(renew_height, reveal_height + state.params.name_max_age - 1);
+    let expiry_height = renew_height + state.params.name_max_age;
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(
+            state.key_to_expiry_height.get(&rtxn, &key).unwrap(),
+            Some(expiry_height)
+        );
+    }
+
+    // The name can't be updated in the block in which it expires.
+    connect_empty(&env, &state, expiry_height - renew_height - 1);
+    let update = TransactionBuilder::default()
+        .spend(outpoint(&renew, 0))
+        .set(address(0), key, Value::from(hash(&"value")))
+        .build();
+    let update = authorize(0, update);
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &update.transaction),
+            Err(Error::BitNames(BitNamesError::NameExpired {
+                expiry_height: height,
+                ..
+            })) if height == expiry_height
+        ));
+    }
+    connect_empty(&env, &state, 1);
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(state.get_value(&rtxn, &key).unwrap(), None);
+        assert_eq!(state.key_to_expiry_height.get(&rtxn, &key).unwrap(), None);
+        assert!(state
+            .get_utxo(&rtxn, &outpoint(&renew, 0))
+            .unwrap()
+            .is_none());
+        assert!(state.expiry_height_to_keys.is_empty(&rtxn).unwrap());
+    }
+
+    // Somebody else can register the name once it expired.
+    let funds = deposit(&env, &state, address(1), 50_000);
+    register(&env, &state, 1, funds, 50_000, key);
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(
+        state.get_value(&rtxn, &key).unwrap(),
+        Some(Value::from([0; 32]))
+    );
+}
+
+#[test]
+fn disconnecting_an_expiry_restores_the_name() {
+    let env = new_env("name-expiry-disconnect");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.params.name_max_age = 5;
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    connect_empty(&env, &state, state.params.name_max_age);
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(
+        state.get_value(&rtxn, &key).unwrap(),
+        Some(Value::from([0; 32]))
+    );
+    assert!(state.get_utxo(&rtxn, &name_outpoint).unwrap().is_some());
+    assert!(state
+        .key_to_expiry_height
+        .get(&rtxn, &key)
+        .unwrap()
+        .is_some());
+}
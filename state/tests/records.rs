@@ -0,0 +1,65 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+/// Validate setting `records` on a freshly registered name.
+fn validate_records(name_: &str, records: Vec<Record>) -> Result<u64, Error> {
+    let env = new_env(&format!("records-{name_}"));
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    let transaction = TransactionBuilder::default()
+        .spend(name_outpoint)
+        .set_records(address(0), key, records)
+        .build();
+    let rtxn = env.read_txn().unwrap();
+    state.validate_transaction(&rtxn, &transaction)
+}
+
+fn txt(length: usize) -> Record {
+    Record::Txt("a".repeat(length))
+}
+
+#[test]
+fn records_within_limits_are_valid() {
+    let records = vec![
+        Record::Ipv4(std::net::Ipv4Addr::LOCALHOST),
+        Record::Ipv6(std::net::Ipv6Addr::LOCALHOST),
+        Record::Ed25519PublicKey([1; 32]),
+        Record::ContentHash(hash(&"content")),
+        txt(MAX_TXT_RECORD_LENGTH),
+    ];
+    assert!(bincode::serialized_size(&records).unwrap() as usize <= MAX_RECORDS_SIZE);
+    validate_records("valid", records).unwrap();
+}
+
+#[test]
+fn too_many_records_are_rejected() {
+    let records = vec![Record::Ed25519PublicKey([1; 32]); MAX_RECORDS + 1];
+    assert!(matches!(
+        validate_records("count", records),
+        Err(Error::BitNames(BitNamesError::TooManyRecords {
+            records,
+            ..
+        })) if records == MAX_RECORDS + 1
+    ));
+}
+
+#[test]
+fn long_txt_records_are_rejected() {
+    assert!(matches!(
+        validate_records("txt", vec![txt(MAX_TXT_RECORD_LENGTH + 1)]),
+        Err(Error::BitNames(BitNamesError::TxtRecordTooLong { .. }))
+    ));
+}
+
+#[test]
+fn large_records_are_rejected() {
+    // Each record is within limits, but together they are too large.
+    let records = vec![txt(MAX_TXT_RECORD_LENGTH); 5];
+    assert!(bincode::serialized_size(&records).unwrap() as usize > MAX_RECORDS_SIZE);
+    assert!(matches!(
+        validate_records("size", records),
+        Err(Error::BitNames(BitNamesError::RecordsTooLarge { .. }))
+    ));
+}
@@ -0,0 +1,89 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+#[test]
+fn export_import_export_is_identical() {
+    let env = new_env("export");
+    let state = BitNamesState::new(&env).unwrap();
+    let mut wtxn = env.write_txn().unwrap();
+    let (_, genesis) = state.get_best_header(&wtxn).unwrap();
+    let body = Body::new(vec![], vec![]);
+    let header = Header {
+        prev_side_block_hash: genesis.block_hash(),
+        prev_main_block_hash: genesis.prev_main_block_hash,
+        merkle_root: body.compute_merkle_root(),
+        state_root: state.get_state_root(&wtxn).unwrap(),
+    };
+    let mut two_way_peg_data = TwoWayPegData::default();
+    two_way_peg_data.deposits.insert(
+        OutPoint::Deposit(bitcoin::OutPoint::default()),
+        Output {
+            address: Address::from([1; 32]),
+            content: Content::Value(100_000),
+        },
+    );
+    state
+        .connect_block(&mut wtxn, &header, &body, &two_way_peg_data)
+        .unwrap();
+    wtxn.commit().unwrap();
+
+    let rtxn = env.read_txn().unwrap();
+    let mut snapshot = vec![];
+    state.export_snapshot(&rtxn, &mut snapshot).unwrap();
+
+    let imported_env = new_env("import");
+    let imported_state = BitNamesState::new(&imported_env).unwrap();
+    let mut wtxn = imported_env.write_txn().unwrap();
+    let (height, block_hash) = imported_state
+        .import_snapshot(&mut wtxn, snapshot.as_slice())
+        .unwrap();
+    wtxn.commit().unwrap();
+    assert_eq!(height, 1);
+    assert_eq!(block_hash, header.block_hash());
+
+    let rtxn = imported_env.read_txn().unwrap();
+    let mut reexported = vec![];
+    imported_state
+        .export_snapshot(&rtxn, &mut reexported)
+        .unwrap();
+    assert_eq!(snapshot, reexported);
+}
+
+#[test]
+fn snapshots_leave_out_block_bodies() {
+    let env = new_env("export-bodies");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.index_transactions = true;
+    let funds = deposit(&env, &state, address(0), 1000);
+    let spend = TransactionBuilder::default()
+        .spend(funds)
+        .value(address(1), 1000)
+        .build();
+    let spend = authorize(0, spend);
+    let header = connect(&env, &state, vec![spend.clone()]).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.get_body(&rtxn, 2).unwrap().is_some());
+    let mut snapshot = vec![];
+    state.export_snapshot(&rtxn, &mut snapshot).unwrap();
+
+    let imported_env = new_env("import-bodies");
+    let imported_state = BitNamesState::new(&imported_env).unwrap();
+    let mut wtxn = imported_env.write_txn().unwrap();
+    imported_state
+        .import_snapshot(&mut wtxn, snapshot.as_slice())
+        .unwrap();
+    wtxn.commit().unwrap();
+    let rtxn = imported_env.read_txn().unwrap();
+    assert!(imported_state.get_body(&rtxn, 2).unwrap().is_none());
+    assert!(imported_state
+        .get_transaction(&rtxn, &spend.transaction.txid())
+        .unwrap()
+        .is_none());
+    // Block hashes are indexed from the imported headers.
+    assert_eq!(
+        imported_state
+            .get_block_height(&rtxn, &header.block_hash())
+            .unwrap(),
+        Some(2)
+    );
+}
@@ -0,0 +1,134 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+fn proof(env: &heed::Env, state: &BitNamesState, key: &Key) -> NameProof {
+    let rtxn = env.read_txn().unwrap();
+    state.get_name_proof(&rtxn, key).unwrap()
+}
+
+#[test]
+fn headers_commit_to_the_state_after_the_block() {
+    let env = new_env("state-tree-root");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    register(&env, &state, 0, funds, 50_000, key);
+    let rtxn = env.read_txn().unwrap();
+    let (_, header) = state.get_best_header(&rtxn).unwrap();
+    assert_ne!(header.state_root, Hash::default());
+    assert_eq!(header.state_root, state.get_state_root(&rtxn).unwrap());
+
+    let proof = state.get_name_proof(&rtxn, &key).unwrap();
+    assert_eq!(proof.value, Some(Value::from([0; 32])));
+    assert!(verify_name_proof(&header.state_root, &proof));
+    let missing = state.get_name_proof(&rtxn, &name("bob")).unwrap();
+    assert_eq!(missing.value, None);
+    assert!(verify_name_proof(&header.state_root, &missing));
+}
+
+#[test]
+fn blocks_with_a_wrong_state_root_are_rejected() {
+    let env = new_env("state-tree-wrong-root");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let salt = Salt::from(hash(&"salt"));
+    let commit = TransactionBuilder::default()
+        .spend(funds)
+        .commit(address(0), key, salt)
+        .value(address(0), 50_000)
+        .build();
+    let commit = authorize(0, commit);
+    connect(&env, &state, vec![commit.clone()]).unwrap();
+    let reveal = TransactionBuilder::default()
+        .spend(outpoint(&commit, 0))
+        .spend(outpoint(&commit, 1))
+        .reveal(address(0), key, salt)
+        .value(address(0), 50_000 - state.params.registration_fee)
+        .build();
+    let body = Body::new(vec![authorize(0, reveal)], vec![]);
+
+    // The root before the block doesn't include the revealed name.
+    let mut wtxn = env.write_txn().unwrap();
+    let (height, prev_header) = state.get_best_header(&wtxn).unwrap();
+    let header = Header {
+        prev_side_block_hash: prev_header.block_hash(),
+        prev_main_block_hash: prev_header.prev_main_block_hash,
+        merkle_root: body.compute_merkle_root(),
+        state_root: state.get_state_root(&wtxn).unwrap(),
+    };
+    state
+        .validate_block(&wtxn, &header, &body, &TwoWayPegData::default())
+        .unwrap();
+    assert!(matches!(
+        state.connect_block(&mut wtxn, &header, &body, &TwoWayPegData::default()),
+        Err(Error::Header(HeaderError::InvalidStateRoot))
+    ));
+    drop(wtxn);
+    assert_eq!(best_height(&env, &state), height);
+
+    let header = connect_body(&env, &state, body, &TwoWayPegData::default()).unwrap();
+    let proof = proof(&env, &state, &key);
+    assert!(verify_name_proof(&header.state_root, &proof));
+}
+
+#[test]
+fn tampered_proofs_are_rejected() {
+    let env = new_env("state-tree-tampered");
+    let state = BitNamesState::new(&env).unwrap();
+    let alice_funds = deposit(&env, &state, address(0), 50_000);
+    let bob_funds = deposit(&env, &state, address(1), 50_000);
+    register(&env, &state, 0, alice_funds, 50_000, name("alice"));
+    register(&env, &state, 1, bob_funds, 50_000, name("bob"));
+    let state_root = {
+        let rtxn = env.read_txn().unwrap();
+        state.get_state_root(&rtxn).unwrap()
+    };
+    let proof = proof(&env, &state, &name("alice"));
+    assert!(verify_name_proof(&state_root, &proof));
+
+    let mut wrong_value = proof.clone();
+    wrong_value.value = Some(Value::from(hash(&"value")));
+    assert!(!verify_name_proof(&state_root, &wrong_value));
+    let mut removed = proof.clone();
+    removed.value = None;
+    assert!(!verify_name_proof(&state_root, &removed));
+    let mut wrong_key = proof.clone();
+    wrong_key.key = name("carol");
+    assert!(!verify_name_proof(&state_root, &wrong_key));
+    // Bob's leaf is the only non empty sibling on the path to alice.
+    let index = proof.siblings.iter().position(Option::is_some).unwrap();
+    let mut wrong_sibling = proof.clone();
+    wrong_sibling.siblings[index] = None;
+    assert!(!verify_name_proof(&state_root, &wrong_sibling));
+    let mut short = proof;
+    short.siblings.pop();
+    assert!(!verify_name_proof(&state_root, &short));
+}
+
+#[test]
+fn removed_names_leave_the_tree() {
+    let env = new_env("state-tree-removal");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    let registered_root = {
+        let rtxn = env.read_txn().unwrap();
+        state.get_state_root(&rtxn).unwrap()
+    };
+    let burn = TransactionBuilder::default().spend(name_outpoint).build();
+    connect(&env, &state, vec![authorize(0, burn)]).unwrap();
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(state.get_state_root(&rtxn).unwrap(), Hash::default());
+        assert!(state.state_tree.is_empty(&rtxn).unwrap());
+    }
+
+    // Disconnecting the block restores the tree.
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(state.get_state_root(&rtxn).unwrap(), registered_root);
+}
@@ -0,0 +1,170 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+/// Transaction of account 0 spending the name `parent` to create `label`.
+fn create_subname(parent_outpoint: OutPoint, parent: Key, label: Key) -> Transaction {
+    TransactionBuilder::default()
+        .spend(parent_outpoint)
+        .transfer(parent, address(0))
+        .subname(address(0), parent, label)
+        .build()
+}
+
+#[test]
+fn only_the_parent_owner_can_create_subnames() {
+    let env = new_env("subnames-authorization");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let alice = name("alice");
+    let (alice_outpoint, change) = register(&env, &state, 0, funds, 50_000, alice);
+
+    // The parent name must be spent.
+    let unspent_parent = TransactionBuilder::default()
+        .spend(change)
+        .subname(address(0), alice, name("www"))
+        .build();
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(matches!(
+            state.validate_transaction(&rtxn, &unspent_parent),
+            Err(Error::BitNames(BitNamesError::ParentNameNotSpent { parent }))
+                if parent == alice
+        ));
+    }
+
+    // And spending it must be authorized by its owner.
+    let subname = create_subname(alice_outpoint, alice, name("www"));
+    assert!(connect(&env, &state, vec![authorize(1, subname.clone())]).is_err());
+    let key = subname_key(&alice, &name("www"));
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(state.get_value(&rtxn, &key).unwrap(), None);
+    }
+    connect(&env, &state, vec![authorize(0, subname)]).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(
+        state.get_value(&rtxn, &key).unwrap(),
+        Some(Value::from([0; 32]))
+    );
+    assert_eq!(state.key_to_parent.get(&rtxn, &key).unwrap(), Some(alice));
+    assert_eq!(
+        state.key_to_subnames.get(&rtxn, &alice).unwrap(),
+        Some(vec![key])
+    );
+}
+
+#[test]
+fn subnames_are_not_keyed_like_names() {
+    let parent = name("alice");
+    let label = name("www");
+    let untagged: Key = hash(&(parent, label)).into();
+    assert_ne!(subname_key(&parent, &label), untagged);
+}
+
+#[test]
+fn revealed_subname_keys_are_replaced_by_the_parent_owner() {
+    let env = new_env("subnames-squatting");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let alice = name("alice");
+    let (alice_outpoint, _) = register(&env, &state, 0, funds, 50_000, alice);
+
+    // Somebody else reveals the key of a subname before it is created.
+    let key = subname_key(&alice, &name("www"));
+    let funds = deposit(&env, &state, address(1), 50_000);
+    let (squatted_outpoint, _) = register(&env, &state, 1, funds, 50_000, key);
+
+    let subname = authorize(0, create_subname(alice_outpoint, alice, name("www")));
+    connect(&env, &state, vec![subname.clone()]).unwrap();
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(state.get_utxo(&rtxn, &squatted_outpoint).unwrap().is_none());
+        assert_eq!(state.key_to_parent.get(&rtxn, &key).unwrap(), Some(alice));
+        let output = state.get_utxo(&rtxn, &outpoint(&subname, 1)).unwrap();
+        assert_eq!(output.unwrap().address, address(0));
+    }
+
+    // Created subnames can't be created again.
+    let again = create_subname(outpoint(&subname, 0), alice, name("www"));
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_transaction(&rtxn, &again),
+        Err(Error::BitNames(
+            BitNamesError::SubnameAlreadyRegistered { .. }
+        ))
+    ));
+}
+
+/// Register "alice" with a name_max_age of 10 and create "www" under it in
+/// the next block. Returns the subname transaction.
+fn register_with_subname(env: &heed::Env, state: &mut BitNamesState) -> AuthorizedTransaction {
+    state.params.name_max_age = 10;
+    let funds = deposit(env, state, address(0), 50_000);
+    let alice = name("alice");
+    let (alice_outpoint, _) = register(env, state, 0, funds, 50_000, alice);
+    let subname = authorize(0, create_subname(alice_outpoint, alice, name("www")));
+    connect(env, state, vec![subname.clone()]).unwrap();
+    subname
+}
+
+#[test]
+fn expiring_subnames_are_unlinked_from_their_parent() {
+    let env = new_env("subnames-expiry");
+    let mut state = BitNamesState::new(&env).unwrap();
+    let subname = register_with_subname(&env, &mut state);
+    let alice = name("alice");
+    let www = subname_key(&alice, &name("www"));
+
+    // Renew the parent, so that only the subname expires.
+    connect_empty(&env, &state, 5);
+    let renew = TransactionBuilder::default()
+        .spend(outpoint(&subname, 0))
+        .transfer(alice, address(0))
+        .build();
+    connect(&env, &state, vec![authorize(0, renew)]).unwrap();
+    connect_empty(&env, &state, 4);
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.get_value(&rtxn, &alice).unwrap().is_some());
+    assert_eq!(state.get_value(&rtxn, &www).unwrap(), None);
+    assert_eq!(state.key_to_parent.get(&rtxn, &www).unwrap(), None);
+    assert_eq!(state.key_to_subnames.get(&rtxn, &alice).unwrap(), None);
+}
+
+#[test]
+fn subnames_of_expired_names_are_unlinked() {
+    let env = new_env("subnames-parent-expiry");
+    let mut state = BitNamesState::new(&env).unwrap();
+    let subname = register_with_subname(&env, &mut state);
+    let alice = name("alice");
+    let www = subname_key(&alice, &name("www"));
+
+    // Renew the subname, so that only the parent expires.
+    connect_empty(&env, &state, 5);
+    let renew = TransactionBuilder::default()
+        .spend(outpoint(&subname, 1))
+        .transfer(www, address(0))
+        .build();
+    connect(&env, &state, vec![authorize(0, renew)]).unwrap();
+    connect_empty(&env, &state, 3);
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert_eq!(state.get_value(&rtxn, &alice).unwrap(), None);
+        assert!(state.get_value(&rtxn, &www).unwrap().is_some());
+        assert_eq!(state.key_to_parent.get(&rtxn, &www).unwrap(), None);
+        assert_eq!(state.key_to_subnames.get(&rtxn, &alice).unwrap(), None);
+    }
+
+    // Whoever registers the parent next can replace the subname.
+    let funds = deposit(&env, &state, address(1), 50_000);
+    let (alice_outpoint, _) = register(&env, &state, 1, funds, 50_000, alice);
+    let replace = TransactionBuilder::default()
+        .spend(alice_outpoint)
+        .transfer(alice, address(1))
+        .subname(address(1), alice, name("www"))
+        .build();
+    connect(&env, &state, vec![authorize(1, replace.clone())]).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(state.key_to_parent.get(&rtxn, &www).unwrap(), Some(alice));
+    let output = state.get_utxo(&rtxn, &outpoint(&replace, 1)).unwrap();
+    assert_eq!(output.unwrap().address, address(1));
+}
@@ -0,0 +1,52 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+#[test]
+fn transfers_keep_value_and_records() {
+    let env = new_env("transfer");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (name_outpoint, _) = register(&env, &state, 0, funds, 50_000, key);
+    let records = vec![Record::Txt("alice".into())];
+    let set_records = TransactionBuilder::default()
+        .spend(name_outpoint)
+        .set_records(address(0), key, records.clone())
+        .build();
+    let set_records = authorize(0, set_records);
+    connect(&env, &state, vec![set_records.clone()]).unwrap();
+    let value = {
+        let rtxn = env.read_txn().unwrap();
+        state.get_value(&rtxn, &key).unwrap()
+    };
+
+    let transfer = TransactionBuilder::default()
+        .spend(outpoint(&set_records, 0))
+        .transfer(key, address(1))
+        .build();
+    let transfer = authorize(0, transfer);
+    connect(&env, &state, vec![transfer.clone()]).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(state.get_value(&rtxn, &key).unwrap(), value);
+    assert_eq!(state.get_records(&rtxn, &key).unwrap(), Some(records));
+    let output = state.get_utxo(&rtxn, &outpoint(&transfer, 0)).unwrap();
+    assert_eq!(output.unwrap().address, address(1));
+}
+
+#[test]
+fn names_must_be_spent_to_be_transferred() {
+    let env = new_env("transfer-unspent");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let (_, change) = register(&env, &state, 0, funds, 50_000, key);
+    let transfer = TransactionBuilder::default()
+        .spend(change)
+        .transfer(key, address(1))
+        .build();
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_transaction(&rtxn, &transfer),
+        Err(Error::BitNames(BitNamesError::InvalidKey { .. }))
+    ));
+}
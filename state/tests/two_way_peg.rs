@@ -0,0 +1,132 @@
+use bitnames_state::bitcoin::hashes::Hash as _;
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+/// Connect an empty block with `two_way_peg_data`, the best block must not
+/// change if it is rejected.
+fn connect_peg_data(
+    env: &heed::Env,
+    state: &BitNamesState,
+    two_way_peg_data: &TwoWayPegData,
+) -> Result<Header, Error> {
+    let height = best_height(env, state);
+    let result = connect_body(env, state, Body::new(vec![], vec![]), two_way_peg_data);
+    if result.is_err() {
+        assert_eq!(best_height(env, state), height);
+    }
+    result
+}
+
+#[test]
+fn deposit_blocks_must_be_processed_in_order() {
+    let env = new_env("peg-order");
+    let state = BitNamesState::new(&env).unwrap();
+    let two_way_peg_data = peg_data(10, &[(address(0), 1000)]);
+    connect_peg_data(&env, &state, &two_way_peg_data).unwrap();
+
+    let mut same_block = peg_data(11, &[(address(0), 1000)]);
+    same_block.deposit_block = two_way_peg_data.deposit_block;
+    assert!(matches!(
+        connect_peg_data(&env, &state, &same_block),
+        Err(Error::BitNames(
+            BitNamesError::DepositBlockAlreadyProcessed { .. }
+        ))
+    ));
+    for main_height in [9, 10] {
+        let mut earlier_block = peg_data(main_height, &[(address(0), 1000)]);
+        earlier_block.deposit_block = Some((bitcoin::BlockHash::from_inner([7; 32]), main_height));
+        assert!(matches!(
+            connect_peg_data(&env, &state, &earlier_block),
+            Err(Error::BitNames(BitNamesError::DepositBlockOutOfOrder {
+                deposit_block_height,
+                last_deposit_block_height: 10,
+                ..
+            })) if deposit_block_height == main_height
+        ));
+    }
+    connect_peg_data(&env, &state, &peg_data(11, &[(address(0), 1000)])).unwrap();
+
+    // Disconnecting the block restores the previous deposit block.
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(
+        state.get_last_deposit_block_hash(&rtxn).unwrap(),
+        two_way_peg_data
+            .deposit_block
+            .map(|(block_hash, _)| block_hash)
+    );
+}
+
+#[test]
+fn invalid_deposits_are_rejected() {
+    let env = new_env("peg-deposits");
+    let state = BitNamesState::new(&env).unwrap();
+    let two_way_peg_data = peg_data(1, &[(address(0), 1000)]);
+    connect_peg_data(&env, &state, &two_way_peg_data).unwrap();
+
+    let mut missing_block = peg_data(2, &[(address(0), 1000)]);
+    missing_block.deposit_block = None;
+    assert!(matches!(
+        connect_peg_data(&env, &state, &missing_block),
+        Err(Error::BitNames(BitNamesError::MissingDepositBlockHash))
+    ));
+
+    let mut regular_outpoint = peg_data(2, &[]);
+    regular_outpoint.deposit_block = Some((bitcoin::BlockHash::from_inner([2; 32]), 2));
+    regular_outpoint.deposits.insert(
+        OutPoint::Regular {
+            txid: Txid::from(hash(&"deposit")),
+            vout: 0,
+        },
+        Output {
+            address: address(0),
+            content: Content::Value(1000),
+        },
+    );
+    assert!(matches!(
+        connect_peg_data(&env, &state, &regular_outpoint),
+        Err(Error::BitNames(
+            BitNamesError::InvalidDepositOutPoint { .. }
+        ))
+    ));
+
+    let mut withdrawal = peg_data(2, &[(address(0), 1000)]);
+    for output in withdrawal.deposits.values_mut() {
+        output.content = Content::Withdrawal {
+            value: 1000,
+            main_fee: 0,
+            main_address: main_address(0),
+        };
+    }
+    assert!(matches!(
+        connect_peg_data(&env, &state, &withdrawal),
+        Err(Error::BitNames(BitNamesError::InvalidDepositOutput { .. }))
+    ));
+
+    let mut existing = peg_data(2, &[]);
+    existing.deposit_block = Some((bitcoin::BlockHash::from_inner([2; 32]), 2));
+    existing.deposits = two_way_peg_data.deposits.clone();
+    assert!(matches!(
+        connect_peg_data(&env, &state, &existing),
+        Err(Error::BitNames(BitNamesError::DepositAlreadyExists { .. }))
+    ));
+}
+
+#[test]
+fn statuses_of_unknown_bundles_are_rejected() {
+    let env = new_env("peg-bundle-statuses");
+    let state = BitNamesState::new(&env).unwrap();
+    let mut two_way_peg_data = TwoWayPegData::default();
+    two_way_peg_data.bundle_statuses.insert(
+        bitcoin::Txid::from_inner([1; 32]),
+        WithdrawalBundleStatus::Failed,
+    );
+    assert!(matches!(
+        connect_peg_data(&env, &state, &two_way_peg_data),
+        Err(Error::BitNames(
+            BitNamesError::UnknownWithdrawalBundle { .. }
+        ))
+    ));
+}
@@ -0,0 +1,102 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+fn transaction(
+    env: &heed::Env,
+    state: &BitNamesState,
+    txid: &Txid,
+) -> Option<ConfirmedTransaction> {
+    let rtxn = env.read_txn().unwrap();
+    state.get_transaction(&rtxn, txid).unwrap()
+}
+
+/// Transaction of account `index` paying `value` from `funds` to account 9.
+fn payment(index: u8, funds: &[OutPoint], value: u64) -> AuthorizedTransaction {
+    let mut builder = TransactionBuilder::default();
+    for outpoint in funds {
+        builder = builder.spend(*outpoint);
+    }
+    authorize(index, builder.value(address(9), value).build())
+}
+
+#[test]
+fn confirmed_transactions_are_found_by_txid() {
+    let env = new_env("txindex-lookup");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.index_transactions = true;
+    let first_funds = deposit(&env, &state, address(0), 1000);
+    let second_funds = [
+        deposit(&env, &state, address(1), 500),
+        deposit(&env, &state, address(1), 500),
+    ];
+    let first = payment(0, &[first_funds], 1000);
+    let second = payment(1, &second_funds, 1000);
+    let header = connect(&env, &state, vec![first.clone(), second.clone()]).unwrap();
+    let height = best_height(&env, &state);
+
+    // Authorizations of the second transaction follow those of the first one
+    // in the body.
+    let confirmed = transaction(&env, &state, &second.transaction.txid()).unwrap();
+    assert_eq!(confirmed.height, height);
+    assert_eq!(confirmed.index, 1);
+    assert_eq!(confirmed.block_hash, header.block_hash());
+    assert_eq!(confirmed.confirmations, 1);
+    assert_eq!(
+        confirmed.transaction.transaction.txid(),
+        second.transaction.txid()
+    );
+    assert_eq!(
+        bincode::serialize(&confirmed.transaction.authorizations).unwrap(),
+        bincode::serialize(&second.authorizations).unwrap()
+    );
+    let spent_values: Vec<_> = confirmed
+        .spent_utxos
+        .iter()
+        .map(|output| output.content.clone())
+        .collect();
+    assert!(matches!(
+        spent_values[..],
+        [Content::Value(500), Content::Value(500)]
+    ));
+    let confirmed = transaction(&env, &state, &first.transaction.txid()).unwrap();
+    assert_eq!(confirmed.index, 0);
+
+    connect_empty(&env, &state, 2);
+    let confirmed = transaction(&env, &state, &first.transaction.txid()).unwrap();
+    assert_eq!(confirmed.confirmations, 3);
+}
+
+#[test]
+fn disconnected_transactions_are_unindexed() {
+    let env = new_env("txindex-disconnect");
+    let mut state = BitNamesState::new(&env).unwrap();
+    state.index_transactions = true;
+    let funds = deposit(&env, &state, address(0), 1000);
+    let payment = payment(0, &[funds], 1000);
+    connect(&env, &state, vec![payment.clone()]).unwrap();
+    let txid = payment.transaction.txid();
+    assert!(transaction(&env, &state, &txid).is_some());
+
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+    assert!(transaction(&env, &state, &txid).is_none());
+    let rtxn = env.read_txn().unwrap();
+    assert!(state.txid_to_location.get(&rtxn, &txid).unwrap().is_none());
+}
+
+#[test]
+fn transactions_are_only_indexed_when_enabled() {
+    let env = new_env("txindex-disabled");
+    let mut state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 1000);
+    let unindexed = payment(0, &[funds], 1000);
+    connect(&env, &state, vec![unindexed.clone()]).unwrap();
+    assert!(transaction(&env, &state, &unindexed.transaction.txid()).is_none());
+
+    state.index_transactions = true;
+    let indexed = payment(9, &[outpoint(&unindexed, 0)], 1000);
+    connect(&env, &state, vec![indexed.clone()]).unwrap();
+    assert!(transaction(&env, &state, &unindexed.transaction.txid()).is_none());
+    assert!(transaction(&env, &state, &indexed.transaction.txid()).is_some());
+}
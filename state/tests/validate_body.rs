@@ -0,0 +1,235 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+#[test]
+fn transactions_are_validated_against_their_own_inputs() {
+    let env = new_env("validate-body-inputs");
+    let state = BitNamesState::new(&env).unwrap();
+    let first = deposit(&env, &state, address(0), 1000);
+    let second = deposit(&env, &state, address(0), 2000);
+    let third = deposit(&env, &state, address(0), 50_000);
+    let fourth = deposit(&env, &state, address(0), 20_000);
+    let key = name("alice");
+    let salt = Salt::from(hash(&"salt"));
+    let commit = TransactionBuilder::default()
+        .spend(third)
+        .commit(address(0), key, salt)
+        .value(address(0), 50_000)
+        .build();
+    let commit = authorize(0, commit);
+    connect(&env, &state, vec![commit.clone()]).unwrap();
+
+    // Transactions with 2, 1 and 2 inputs, each paying a different fee.
+    let transactions = vec![
+        TransactionBuilder::default()
+            .spend(first)
+            .spend(second)
+            .value(address(1), 2900)
+            .build(),
+        TransactionBuilder::default()
+            .spend(outpoint(&commit, 1))
+            .value(address(1), 49_000)
+            .build(),
+        TransactionBuilder::default()
+            .spend(outpoint(&commit, 0))
+            .spend(fourth)
+            .reveal(address(0), key, salt)
+            .value(address(0), 20_000 - state.params.registration_fee)
+            .build(),
+    ];
+    let transactions: Vec<_> = transactions
+        .into_iter()
+        .map(|transaction| authorize(0, transaction))
+        .collect();
+    let body = Body::new(transactions.clone(), vec![]);
+    {
+        let rtxn = env.read_txn().unwrap();
+        let fees = state.validate_body(&rtxn, &body).unwrap();
+        assert_eq!(fees, 100 + 1000 + state.params.registration_fee);
+    }
+    connect(&env, &state, transactions).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert_eq!(
+        state.get_value(&rtxn, &key).unwrap(),
+        Some(Value::from([0; 32]))
+    );
+}
+
+#[test]
+fn transactions_are_validated_at_the_next_height() {
+    let env = new_env("validate-body-height");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 50_000);
+    let key = name("alice");
+    let salt = Salt::from(hash(&"salt"));
+    let commit = TransactionBuilder::default()
+        .spend(funds)
+        .commit(address(0), key, salt)
+        .value(address(0), 50_000)
+        .build();
+    let commit = authorize(0, commit);
+    connect(&env, &state, vec![commit.clone()]).unwrap();
+    let commitment_height = best_height(&env, &state);
+    let reveal = TransactionBuilder::default()
+        .spend(outpoint(&commit, 0))
+        .spend(outpoint(&commit, 1))
+        .reveal(address(0), key, salt)
+        .value(address(0), 50_000 - state.params.registration_fee)
+        .build();
+    let body = Body::new(vec![authorize(0, reveal)], vec![]);
+
+    // The next block is the last one in which the commitment can be revealed.
+    connect_empty(&env, &state, COMMITMENT_MAX_AGE - 1);
+    assert_eq!(
+        best_height(&env, &state) + 1,
+        commitment_height + COMMITMENT_MAX_AGE
+    );
+    {
+        let rtxn = env.read_txn().unwrap();
+        state.validate_body(&rtxn, &body).unwrap();
+    }
+    connect_empty(&env, &state, 1);
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_body(&rtxn, &body),
+        Err(Error::BitNames(BitNamesError::RevealTooLate {
+            late_by: 1,
+            ..
+        }))
+    ));
+}
+
+#[test]
+fn transactions_can_spend_outputs_of_earlier_transactions() {
+    let env = new_env("validate-body-chained");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 1000);
+    let parent = TransactionBuilder::default()
+        .spend(funds)
+        .value(address(1), 900)
+        .build();
+    let parent = authorize(0, parent);
+    let child = TransactionBuilder::default()
+        .spend(outpoint(&parent, 0))
+        .value(address(2), 800)
+        .build();
+    let child = authorize(1, child);
+    {
+        let rtxn = env.read_txn().unwrap();
+        // Children must come after their parents.
+        let reversed = Body::new(vec![child.clone(), parent.clone()], vec![]);
+        assert!(state.validate_body(&rtxn, &reversed).is_err());
+        let body = Body::new(vec![parent.clone(), child.clone()], vec![]);
+        assert_eq!(state.validate_body(&rtxn, &body).unwrap(), 200);
+    }
+    connect(&env, &state, vec![parent.clone(), child.clone()]).unwrap();
+    let rtxn = env.read_txn().unwrap();
+    assert!(state
+        .get_utxo(&rtxn, &outpoint(&parent, 0))
+        .unwrap()
+        .is_none());
+    assert!(state
+        .get_utxo(&rtxn, &outpoint(&child, 0))
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn oversized_bodies_are_rejected() {
+    let env = new_env("validate-body-size");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 100_000);
+    let mut builder = TransactionBuilder::default().spend(funds);
+    for _ in 0..5000 {
+        builder = builder.value(address(1), 1);
+    }
+    let body = Body::new(vec![authorize(0, builder.build())], vec![]);
+    assert!(bincode::serialized_size(&body).unwrap() as usize > BLOCK_SIZE_LIMIT);
+    let height = best_height(&env, &state);
+    assert!(matches!(
+        connect_body(&env, &state, body, &TwoWayPegData::default()),
+        Err(Error::BitNames(BitNamesError::BlockTooLarge { .. }))
+    ));
+    assert_eq!(best_height(&env, &state), height);
+}
+
+/// Body with a transaction of account 0 paying a fee of 100 from `funds`
+/// holding 1000, and `coinbase`.
+fn body_with_coinbase(funds: OutPoint, coinbase: Vec<Output>) -> Body {
+    let payment = TransactionBuilder::default()
+        .spend(funds)
+        .value(address(1), 900)
+        .build();
+    Body::new(vec![authorize(0, payment)], coinbase)
+}
+
+#[test]
+fn coinbase_value_is_limited_to_fees() {
+    let env = new_env("validate-body-coinbase-value");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 1000);
+    let coinbase = |values: &[u64]| {
+        values
+            .iter()
+            .map(|value| Output {
+                address: address(2),
+                content: Content::Value(*value),
+            })
+            .collect::<Vec<_>>()
+    };
+    let rtxn = env.read_txn().unwrap();
+    let body = body_with_coinbase(funds, coinbase(&[60, 40]));
+    assert_eq!(state.validate_body(&rtxn, &body).unwrap(), 100);
+    let body = body_with_coinbase(funds, coinbase(&[60, 41]));
+    assert!(matches!(
+        state.validate_body(&rtxn, &body),
+        Err(Error::BitNames(BitNamesError::CoinbaseExceedsFees {
+            coinbase_value: 101,
+            fees: 100,
+        }))
+    ));
+    let body = body_with_coinbase(funds, coinbase(&[u64::MAX, 1]));
+    assert!(matches!(
+        state.validate_body(&rtxn, &body),
+        Err(Error::BitNames(BitNamesError::CoinbaseValueOverflow))
+    ));
+}
+
+#[test]
+fn coinbase_outputs_only_hold_value() {
+    let env = new_env("validate-body-coinbase-content");
+    let state = BitNamesState::new(&env).unwrap();
+    let funds = deposit(&env, &state, address(0), 1000);
+    let key = name("alice");
+    let coinbase = vec![Output {
+        address: address(2),
+        content: Content::Custom(BitNamesOutput::Reveal {
+            salt: Salt::from(hash(&"salt")),
+            key,
+        }),
+    }];
+    let body = body_with_coinbase(funds, coinbase);
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_body(&rtxn, &body),
+        Err(Error::BitNames(BitNamesError::InvalidCoinbaseOutput))
+    ));
+}
+
+#[test]
+fn block_fees_must_not_overflow() {
+    let env = new_env("validate-body-fees-overflow");
+    let state = BitNamesState::new(&env).unwrap();
+    let transactions: Vec<_> = (0..2)
+        .map(|_| {
+            let funds = deposit(&env, &state, address(0), u64::MAX);
+            authorize(0, TransactionBuilder::default().spend(funds).build())
+        })
+        .collect();
+    let body = Body::new(transactions, vec![]);
+    let rtxn = env.read_txn().unwrap();
+    assert!(matches!(
+        state.validate_body(&rtxn, &body),
+        Err(Error::BitNames(BitNamesError::FeesOverflow))
+    ));
+}
@@ -0,0 +1,186 @@
+use bitnames_state::bitcoin::hashes::Hash as _;
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+use std::collections::HashMap;
+
+fn withdrawals() -> Vec<(OutPoint, Output)> {
+    (0u8..20)
+        .map(|index| {
+            let outpoint = OutPoint::Deposit(bitcoin::OutPoint {
+                txid: bitcoin::Txid::from_inner([index; 32]),
+                vout: index as u32,
+            });
+            let output = Output {
+                address: Address::from([index; 32]),
+                content: Content::Withdrawal {
+                    value: 1000 * (index as u64 % 3 + 1),
+                    main_fee: index as u64 % 4,
+                    main_address: main_address(index % 7),
+                },
+            };
+            (outpoint, output)
+        })
+        .collect()
+}
+
+fn collect_bundle(name: &str, withdrawals: &[(OutPoint, Output)]) -> WithdrawalBundle {
+    let env = new_env(name);
+    let state = BitNamesState::new(&env).unwrap();
+    let mut wtxn = env.write_txn().unwrap();
+    for (outpoint, output) in withdrawals {
+        state.utxos.put(&mut wtxn, outpoint, output).unwrap();
+    }
+    state.collect_withdrawal_bundle(&wtxn).unwrap().unwrap()
+}
+
+#[test]
+fn bundle_does_not_depend_on_insertion_order() {
+    let mut withdrawals = withdrawals();
+    let bundle = collect_bundle("bundle-forward", &withdrawals);
+    withdrawals.reverse();
+    let reversed_bundle = collect_bundle("bundle-reverse", &withdrawals);
+    assert_eq!(bundle.transaction, reversed_bundle.transaction);
+    assert_eq!(
+        bundle
+            .spent_utxos
+            .keys()
+            .collect::<std::collections::HashSet<_>>(),
+        reversed_bundle
+            .spent_utxos
+            .keys()
+            .collect::<std::collections::HashSet<_>>()
+    );
+}
+
+#[test]
+fn bundle_is_identical_across_runs() {
+    let withdrawals = withdrawals();
+    let bundle = collect_bundle("bundle-first-run", &withdrawals);
+    let second_bundle = collect_bundle("bundle-second-run", &withdrawals);
+    assert_eq!(bundle.transaction, second_bundle.transaction);
+}
+
+#[test]
+fn aggregated_withdrawals_sort_by_fee_value_and_address() {
+    let aggregated = |main_fee, value, index| AggregatedWithdrawal {
+        spent_utxos: HashMap::new(),
+        main_address: main_address(index),
+        value,
+        main_fee,
+    };
+    let sorted = vec![
+        aggregated(1, 100, 0),
+        aggregated(1, 100, 1),
+        aggregated(1, 200, 0),
+        aggregated(2, 50, 0),
+        aggregated(2, 50, 2),
+    ];
+    let mut reversed = sorted.clone();
+    reversed.reverse();
+    reversed.sort();
+    assert_eq!(reversed, sorted);
+    let mut rotated = sorted.clone();
+    rotated.rotate_left(2);
+    rotated.sort();
+    assert_eq!(rotated, sorted);
+}
+
+const GAP: u32 = BitNamesState::WITHDRAWAL_BUNDLE_FAILURE_GAP;
+
+fn pending_bundle(env: &heed::Env, state: &BitNamesState) -> Option<WithdrawalBundle> {
+    let rtxn = env.read_txn().unwrap();
+    state.get_pending_withdrawal_bundle(&rtxn).unwrap()
+}
+
+fn bundle_info(
+    env: &heed::Env,
+    state: &BitNamesState,
+    txid: bitcoin::Txid,
+) -> WithdrawalBundleInfo {
+    let rtxn = env.read_txn().unwrap();
+    let bundles = state.get_withdrawal_bundles(&rtxn).unwrap();
+    let (_, info) = bundles
+        .into_iter()
+        .find(|(bundle_txid, _)| *bundle_txid == txid)
+        .unwrap();
+    info
+}
+
+#[test]
+fn one_bundle_is_pending_at_a_time() {
+    let env = new_env("bundle-pending");
+    let state = BitNamesState::new(&env).unwrap();
+    let first = withdraw(&env, &state, 0, 1000);
+    connect_until(&env, &state, GAP + 1);
+    let bundle = pending_bundle(&env, &state).unwrap();
+    assert!(bundle.spent_utxos.contains_key(&first));
+    let txid = bundle.transaction.txid();
+    assert_eq!(bundle_info(&env, &state, txid).creation_height, GAP + 1);
+
+    // Withdrawals created while a bundle is pending wait for it.
+    let second = withdraw(&env, &state, 1, 1000);
+    connect_empty(&env, &state, 1);
+    let pending = pending_bundle(&env, &state).unwrap();
+    assert_eq!(pending.transaction.txid(), txid);
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(state.get_utxo(&rtxn, &second).unwrap().is_some());
+        assert_eq!(state.get_withdrawal_bundles(&rtxn).unwrap().len(), 1);
+    }
+
+    // Once it is confirmed, the next bundle is created in the following
+    // block.
+    let confirmed = WithdrawalBundleStatus::Confirmed {
+        main_block_height: 5,
+    };
+    connect_body(
+        &env,
+        &state,
+        Body::new(vec![], vec![]),
+        &bundle_status(txid, confirmed),
+    )
+    .unwrap();
+    assert!(pending_bundle(&env, &state).is_none());
+    assert!(matches!(
+        bundle_info(&env, &state, txid).status,
+        Some(WithdrawalBundleStatus::Confirmed {
+            main_block_height: 5
+        })
+    ));
+    connect_empty(&env, &state, 1);
+    let next = pending_bundle(&env, &state).unwrap();
+    assert_eq!(next.spent_utxos.keys().collect::<Vec<_>>(), vec![&second]);
+}
+
+#[test]
+fn failed_bundles_are_cleared() {
+    let env = new_env("bundle-failure");
+    let state = BitNamesState::new(&env).unwrap();
+    let withdrawal = withdraw(&env, &state, 0, 1000);
+    connect_until(&env, &state, GAP + 1);
+    let txid = pending_bundle(&env, &state).unwrap().transaction.txid();
+    connect_body(
+        &env,
+        &state,
+        Body::new(vec![], vec![]),
+        &bundle_status(txid, WithdrawalBundleStatus::Failed),
+    )
+    .unwrap();
+    let failure_height = best_height(&env, &state);
+    assert!(pending_bundle(&env, &state).is_none());
+    assert!(matches!(
+        bundle_info(&env, &state, txid).status,
+        Some(WithdrawalBundleStatus::Failed)
+    ));
+    {
+        let rtxn = env.read_txn().unwrap();
+        assert!(state.get_utxo(&rtxn, &withdrawal).unwrap().is_some());
+    }
+
+    // Restored withdrawals are bundled again once the failure gap passed.
+    connect_until(&env, &state, failure_height + GAP);
+    assert!(pending_bundle(&env, &state).is_none());
+    connect_empty(&env, &state, 1);
+    let bundle = pending_bundle(&env, &state).unwrap();
+    assert!(bundle.spent_utxos.contains_key(&withdrawal));
+}
@@ -0,0 +1,117 @@
+use bitnames_state::test_utils::*;
+use bitnames_state::*;
+
+const GAP: u32 = BitNamesState::WITHDRAWAL_BUNDLE_FAILURE_GAP;
+
+fn status(env: &heed::Env, state: &BitNamesState, outpoint: &OutPoint) -> Option<WithdrawalStatus> {
+    let rtxn = env.read_txn().unwrap();
+    state.get_withdrawal_status(&rtxn, outpoint).unwrap()
+}
+
+/// Connect blocks until the first bundle is created, returns its txid.
+fn bundle(env: &heed::Env, state: &BitNamesState) -> bitcoin::Txid {
+    connect_until(env, state, GAP + 1);
+    let rtxn = env.read_txn().unwrap();
+    let bundle = state.get_pending_withdrawal_bundle(&rtxn).unwrap().unwrap();
+    bundle.transaction.txid()
+}
+
+fn disconnect(env: &heed::Env, state: &BitNamesState) {
+    let mut wtxn = env.write_txn().unwrap();
+    state.disconnect_block(&mut wtxn).unwrap();
+    wtxn.commit().unwrap();
+}
+
+#[test]
+fn withdrawals_follow_their_bundle() {
+    let env = new_env("withdrawal-status-confirmed");
+    let state = BitNamesState::new(&env).unwrap();
+    let withdrawal = withdraw(&env, &state, 0, 1000);
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Pending)
+    ));
+    let txid = bundle(&env, &state);
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Bundled { txid: bundle_txid }) if bundle_txid == txid
+    ));
+
+    let confirmed = WithdrawalBundleStatus::Confirmed {
+        main_block_height: 7,
+    };
+    connect_body(
+        &env,
+        &state,
+        Body::new(vec![], vec![]),
+        &bundle_status(txid, confirmed),
+    )
+    .unwrap();
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Confirmed {
+            txid: bundle_txid,
+            main_block_height: 7,
+        }) if bundle_txid == txid
+    ));
+    let rtxn = env.read_txn().unwrap();
+    let (bundle_txid, _) = state
+        .get_outpoint_withdrawal_bundle(&rtxn, &withdrawal)
+        .unwrap()
+        .unwrap();
+    assert_eq!(bundle_txid, txid);
+    drop(rtxn);
+
+    // Disconnecting the confirmation puts the withdrawal back in the bundle.
+    disconnect(&env, &state);
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Bundled { .. })
+    ));
+}
+
+#[test]
+fn withdrawals_of_failed_bundles_are_refunded() {
+    let env = new_env("withdrawal-status-refunded");
+    let state = BitNamesState::new(&env).unwrap();
+    let withdrawal = withdraw(&env, &state, 0, 1000);
+    let txid = bundle(&env, &state);
+    connect_body(
+        &env,
+        &state,
+        Body::new(vec![], vec![]),
+        &bundle_status(txid, WithdrawalBundleStatus::Failed),
+    )
+    .unwrap();
+    let failure_height = best_height(&env, &state);
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Refunded { txid: bundle_txid }) if bundle_txid == txid
+    ));
+
+    // Refunded withdrawals are bundled again after the failure gap.
+    connect_until(&env, &state, failure_height + GAP + 1);
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Bundled { .. })
+    ));
+}
+
+#[test]
+fn spent_withdrawals_have_no_status() {
+    let env = new_env("withdrawal-status-spent");
+    let state = BitNamesState::new(&env).unwrap();
+    let withdrawal = withdraw(&env, &state, 0, 1000);
+    let spend = TransactionBuilder::default()
+        .spend(withdrawal)
+        .value(address(0), 1000)
+        .build();
+    connect(&env, &state, vec![authorize(0, spend)]).unwrap();
+    assert!(status(&env, &state, &withdrawal).is_none());
+
+    disconnect(&env, &state);
+    assert!(matches!(
+        status(&env, &state, &withdrawal),
+        Some(WithdrawalStatus::Pending)
+    ));
+}
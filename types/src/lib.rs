@@ -1,4 +1,5 @@
 mod hashes;
+pub mod light;
 mod types;
 
 pub use hashes::*;
@@ -0,0 +1,226 @@
+//! Name resolution for clients that follow sidechain headers without running
+//! a full node.
+use crate::*;
+use bitcoin::hashes::{Hash as _, HashEngine as _};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Start of the coinbase output script in which a mainchain block commits to
+/// the hash of a sidechain header: `OP_RETURN`, a push of 36 bytes and the
+/// BMM message header, followed by the 32 byte hash.
+const BMM_COMMITMENT_PREFIX: [u8; 6] = [0x6a, 0x24, 0xd1, 0x61, 0x73, 0x68];
+
+/// Coinbase output script committing to the sidechain block `block_hash`.
+pub fn bmm_commitment_script(block_hash: &BlockHash) -> bitcoin::Script {
+    let block_hash: [u8; 32] = (*block_hash).into();
+    let mut script = BMM_COMMITMENT_PREFIX.to_vec();
+    script.extend_from_slice(&block_hash);
+    bitcoin::Script::from(script)
+}
+
+/// Evidence that a mainchain block committed to a sidechain header, supplied
+/// by an untrusted node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BmmProof {
+    /// Header of the mainchain block after the `prev_main_block_hash` of the
+    /// sidechain header.
+    pub main_header: bitcoin::BlockHeader,
+    pub coinbase: bitcoin::Transaction,
+    /// Siblings on the path from the coinbase to the merkle root of
+    /// `main_header`, lowest first.
+    pub merkle_branch: Vec<bitcoin::TxMerkleNode>,
+}
+
+impl BmmProof {
+    /// Check that `main_header` commits to `coinbase`, and `coinbase` to
+    /// `block_hash`.
+    fn verify(&self, block_hash: &BlockHash) -> bool {
+        if !self.coinbase.is_coin_base() {
+            return false;
+        }
+        // The coinbase is the first transaction, so it is always on the left.
+        let mut node = bitcoin::TxMerkleNode::from_hash(self.coinbase.txid().as_hash());
+        for sibling in &self.merkle_branch {
+            let mut engine = bitcoin::TxMerkleNode::engine();
+            engine.input(&node[..]);
+            engine.input(&sibling[..]);
+            node = bitcoin::TxMerkleNode::from_engine(engine);
+        }
+        let script = bmm_commitment_script(block_hash);
+        node == self.main_header.merkle_root
+            && self
+                .coinbase
+                .output
+                .iter()
+                .any(|output| output.script_pubkey == script)
+    }
+}
+
+/// Chain of sidechain headers starting at genesis.
+#[derive(Debug, Clone)]
+pub struct LightClient {
+    headers: Vec<Header>,
+    /// Mainchain blocks that committed to the headers after genesis.
+    main_blocks: Vec<bitcoin::BlockHash>,
+}
+
+impl Default for LightClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LightClient {
+    pub fn new() -> Self {
+        Self {
+            headers: vec![Header::genesis()],
+            main_blocks: vec![],
+        }
+    }
+
+    pub fn get_best_header(&self) -> (u32, &Header) {
+        let height = self.headers.len() as u32 - 1;
+        (height, &self.headers[height as usize])
+    }
+
+    pub fn get_header(&self, height: u32) -> Option<&Header> {
+        self.headers.get(height as usize)
+    }
+
+    /// Connect `headers` with proofs that they were mined, none of them are
+    /// added if any is invalid.
+    ///
+    /// `main_chain` is the best mainchain in height order, from a client that
+    /// verifies mainchain headers, and must include the blocks the current
+    /// and the new headers were mined on. Headers mined on blocks that are no
+    /// longer in it are disconnected first.
+    ///
+    /// The first header can extend any known header. Headers after it are
+    /// replaced if the new tip was mined on a later mainchain block than the
+    /// current one.
+    pub fn connect_headers(
+        &mut self,
+        headers: &[(Header, BmmProof)],
+        main_chain: &[bitcoin::BlockHash],
+    ) -> Result<(), Error> {
+        let main_heights: HashMap<bitcoin::BlockHash, usize> = main_chain
+            .iter()
+            .enumerate()
+            .map(|(main_height, main_block_hash)| (*main_block_hash, main_height))
+            .collect();
+        if let Some(stale) = self
+            .main_blocks
+            .iter()
+            .position(|main_block_hash| !main_heights.contains_key(main_block_hash))
+        {
+            self.headers.truncate(stale + 1);
+            self.main_blocks.truncate(stale);
+        }
+        let first = match headers.first() {
+            Some((header, _)) => header,
+            None => return Ok(()),
+        };
+        let fork_height = self
+            .headers
+            .iter()
+            .rposition(|header| header.block_hash() == first.prev_side_block_hash)
+            .ok_or(Error::UnknownPrevSideBlock)?;
+        let mut prev_header = &self.headers[fork_height];
+        let mut prev_main_height = match fork_height {
+            0 => None,
+            _ => Some(main_heights[&self.main_blocks[fork_height - 1]]),
+        };
+        let mut main_blocks = vec![];
+        for (index, (header, proof)) in headers.iter().enumerate() {
+            let height = (fork_height + index + 1) as u32;
+            if header.prev_side_block_hash != prev_header.block_hash() {
+                return Err(Error::InvalidPrevSideBlockHash { height });
+            }
+            let main_height =
+                *main_heights
+                    .get(&header.prev_main_block_hash)
+                    .ok_or(Error::UnknownMainBlock {
+                        height,
+                        main_block_hash: header.prev_main_block_hash,
+                    })?
+                    + 1;
+            // A mainchain block can commit to only one sidechain block.
+            if let Some(prev_main_height) = prev_main_height {
+                if main_height <= prev_main_height {
+                    return Err(Error::InvalidMainBlockOrder { height });
+                }
+            }
+            let main_block_hash = proof.main_header.block_hash();
+            if main_chain.get(main_height) != Some(&main_block_hash)
+                || proof.main_header.prev_blockhash != header.prev_main_block_hash
+            {
+                return Err(Error::WrongMainBlock {
+                    height,
+                    main_block_hash,
+                });
+            }
+            if !proof.verify(&header.block_hash()) {
+                return Err(Error::InvalidBmmProof { height });
+            }
+            prev_main_height = Some(main_height);
+            main_blocks.push(main_block_hash);
+            prev_header = header;
+        }
+        if fork_height < self.main_blocks.len() {
+            let best_main_height = main_heights[self.main_blocks.last().unwrap()];
+            if prev_main_height <= Some(best_main_height) {
+                return Err(Error::NotBestChain);
+            }
+        }
+        self.headers.truncate(fork_height + 1);
+        self.main_blocks.truncate(fork_height);
+        self.headers
+            .extend(headers.iter().map(|(header, _)| header.clone()));
+        self.main_blocks.extend(main_blocks);
+        Ok(())
+    }
+
+    /// Resolve `key` with a proof from a node, the value is the one the name
+    /// has after the best block was connected.
+    pub fn verify_name(&self, key: &Key, proof: &NameProof) -> Result<Option<Value>, Error> {
+        if proof.key != *key {
+            return Err(Error::WrongKey {
+                key: *key,
+                proof_key: proof.key,
+            });
+        }
+        let (_, header) = self.get_best_header();
+        if !verify_name_proof(&header.state_root, proof) {
+            return Err(Error::InvalidNameProof { key: *key });
+        }
+        Ok(proof.value)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("first header doesn't extend a known header")]
+    UnknownPrevSideBlock,
+    #[error("header {height} doesn't extend the previous header")]
+    InvalidPrevSideBlockHash { height: u32 },
+    #[error("header {height} was mined on unknown mainchain block {main_block_hash}")]
+    UnknownMainBlock {
+        height: u32,
+        main_block_hash: bitcoin::BlockHash,
+    },
+    #[error("header {height} was mined before the previous header")]
+    InvalidMainBlockOrder { height: u32 },
+    #[error("header {height} is committed in {main_block_hash}, which doesn't follow the block it was mined on")]
+    WrongMainBlock {
+        height: u32,
+        main_block_hash: bitcoin::BlockHash,
+    },
+    #[error("invalid BMM proof for header {height}")]
+    InvalidBmmProof { height: u32 },
+    #[error("headers were mined before the best header")]
+    NotBestChain,
+    #[error("proof is for name {proof_key}, not {key}")]
+    WrongKey { key: Key, proof_key: Key },
+    #[error("invalid proof for name {key}")]
+    InvalidNameProof { key: Key },
+}
@@ -9,8 +9,92 @@ use std::collections::HashMap;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BitNamesOutput {
     Commitment(Commitment),
-    Reveal { salt: Salt, key: Key },
-    KeyValue { key: Key, value: Value },
+    Reveal {
+        salt: Salt,
+        key: Key,
+    },
+    KeyValue {
+        key: Key,
+        value: Value,
+    },
+    Transfer {
+        key: Key,
+    },
+    KeyRecords {
+        key: Key,
+        records: Vec<Record>,
+    },
+    Subname {
+        parent: Key,
+        label: Key,
+    },
+    Bid {
+        key: Key,
+        blinded_bid: Hash,
+        deposit: u64,
+    },
+    RevealedBid {
+        key: Key,
+        salt: Salt,
+        amount: u64,
+        deposit: u64,
+    },
+}
+
+/// Commitment to a bid `amount` hidden in a `Bid` output.
+pub fn blind_bid(key: &Key, amount: u64, salt: &Salt) -> Hash {
+    hash(&(key, amount, salt))
+}
+
+/// Sealed bid auction for a name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Auction {
+    pub start_height: u32,
+    /// Highest revealed bid amount and outpoint of its `RevealedBid` output.
+    pub winner: Option<(u64, OutPoint)>,
+    /// Second highest revealed bid amount, paid by the winner.
+    pub price: u64,
+    /// Outpoints of bids that were not revealed yet, they are forfeited when
+    /// the auction ends.
+    pub bids: Vec<OutPoint>,
+}
+
+/// Consensus rules that are not fixed by the transaction format.
+#[derive(Debug, Clone)]
+pub struct ConsensusParams {
+    /// Fee in sats that a transaction must pay for every `Reveal` output.
+    pub registration_fee: u64,
+    /// Number of blocks after the last update at which a name expires.
+    pub name_max_age: u32,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self {
+            registration_fee: 10_000,
+            name_max_age: 52_560,
+        }
+    }
+}
+
+/// Key of the subname `label` of `parent`.
+///
+/// Tagged, so that subname keys can't collide with keys hashed from a name.
+pub fn subname_key(parent: &Key, label: &Key) -> Key {
+    hash(&(SUBNAME_KEY_TAG, parent, label)).into()
+}
+
+const SUBNAME_KEY_TAG: &str = "bitnames/subname";
+
+/// Typed data a name can point to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Record {
+    Ipv4(std::net::Ipv4Addr),
+    Ipv6(std::net::Ipv6Addr),
+    Txt(String),
+    Ed25519PublicKey([u8; 32]),
+    MainchainAddress(bitcoin::Address),
+    ContentHash(Hash),
 }
 
 pub type Output = sdk_types::Output<BitNamesOutput>;
@@ -21,7 +105,10 @@ pub type Body = sdk_types::Body<Authorization, BitNamesOutput>;
 impl GetValue for BitNamesOutput {
     #[inline(always)]
     fn get_value(&self) -> u64 {
-        0
+        match self {
+            Self::Bid { deposit, .. } | Self::RevealedBid { deposit, .. } => *deposit,
+            _ => 0,
+        }
     }
 }
 
@@ -30,6 +117,8 @@ pub struct Header {
     pub prev_side_block_hash: BlockHash,
     pub prev_main_block_hash: bitcoin::BlockHash,
     pub merkle_root: MerkleRoot,
+    /// Root of the state tree after this block is connected.
+    pub state_root: Hash,
 }
 
 impl Header {
@@ -42,14 +131,107 @@ impl Header {
             prev_main_block_hash: bitcoin::BlockHash::from_inner([0; 32]),
             prev_side_block_hash: Default::default(),
             merkle_root: Default::default(),
+            state_root: Default::default(),
         }
     }
 }
 
+/// Depth of the sparse Merkle tree over `key_to_value`, one level per key bit.
+pub const STATE_TREE_DEPTH: usize = 256;
+
+/// Hash of the state tree leaf of a name with `value`, leaves of names that
+/// don't exist are zero.
+pub fn state_tree_leaf(key: &Key, value: &Value) -> Hash {
+    hash(&(key, value))
+}
+
+/// Hash of a state tree node, nodes of empty subtrees are zero.
+pub fn state_tree_node(left: &Hash, right: &Hash) -> Hash {
+    if *left == Hash::default() && *right == Hash::default() {
+        Hash::default()
+    } else {
+        hash(&(left, right))
+    }
+}
+
+/// Whether the path to `key` goes right below the node at `depth`.
+pub fn state_tree_bit(key: &Key, depth: usize) -> bool {
+    let key: &Hash = key.into();
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// Proof that a name has `value` in the state tree, or doesn't exist if
+/// `value` is `None`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameProof {
+    pub key: Key,
+    pub value: Option<Value>,
+    /// Siblings of the nodes on the path from the leaf to the root, `None`
+    /// for empty subtrees.
+    pub siblings: Vec<Option<Hash>>,
+}
+
+/// Check `proof` against the state root committed in a header.
+pub fn verify_name_proof(state_root: &Hash, proof: &NameProof) -> bool {
+    if proof.siblings.len() != STATE_TREE_DEPTH {
+        return false;
+    }
+    let mut node = match &proof.value {
+        Some(value) => state_tree_leaf(&proof.key, value),
+        None => Hash::default(),
+    };
+    for (depth, sibling) in (0..STATE_TREE_DEPTH).rev().zip(&proof.siblings) {
+        let sibling = sibling.unwrap_or_default();
+        node = if state_tree_bit(&proof.key, depth) {
+            state_tree_node(&sibling, &node)
+        } else {
+            state_tree_node(&node, &sibling)
+        };
+    }
+    node == *state_root
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum WithdrawalBundleStatus {
     Failed,
-    Confirmed,
+    Confirmed { main_block_height: u32 },
+}
+
+/// Record of a withdrawal bundle, kept after it is confirmed or failed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalBundleInfo {
+    /// `None` while the bundle is pending on mainchain.
+    pub status: Option<WithdrawalBundleStatus>,
+    pub creation_height: u32,
+    /// Withdrawal outpoints paid by the bundle, in inputs commitment order.
+    pub outpoints: Vec<OutPoint>,
+}
+
+/// Mainchain voting progress of a pending withdrawal bundle.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct WithdrawalBundleScore {
+    pub txid: bitcoin::Txid,
+    /// Mainchain blocks left before the bundle fails.
+    pub blocks_left: u32,
+    /// Acks the bundle received from mainchain miners.
+    pub work_score: u32,
+}
+
+/// Lifecycle of a withdrawal output.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum WithdrawalStatus {
+    /// Waiting to be included in a bundle.
+    Pending,
+    /// Included in the bundle `txid`, which is pending on mainchain.
+    Bundled { txid: bitcoin::Txid },
+    /// Paid out on mainchain by the bundle `txid`.
+    Confirmed {
+        txid: bitcoin::Txid,
+        main_block_height: u32,
+    },
+    /// The bundle `txid` failed and the output was restored, it will be
+    /// included in a later bundle.
+    Refunded { txid: bitcoin::Txid },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,18 +243,71 @@ pub struct WithdrawalBundle {
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct TwoWayPegData {
     pub deposits: HashMap<OutPoint, Output>,
-    pub deposit_block_hash: Option<bitcoin::BlockHash>,
+    /// Hash and height of the mainchain block with the last deposit.
+    pub deposit_block: Option<(bitcoin::BlockHash, u32)>,
     pub bundle_statuses: HashMap<bitcoin::Txid, WithdrawalBundleStatus>,
 }
 
+/// A value that a key had, and the output that set it.
+///
+/// Entries without a value mark names that were removed, by spending them
+/// without a new output or by expiry, and hold the last output of the name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyHistoryEntry {
+    pub height: u32,
+    pub outpoint: OutPoint,
+    pub address: Address,
+    pub value: Option<Value>,
+}
+
+/// Position of a confirmed transaction, and the outputs it spent.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionLocation {
+    pub height: u32,
+    /// Index of the transaction in the block body.
+    pub index: u32,
+    /// Spent outputs in inputs order, they are gone from the utxo set.
+    pub spent_utxos: Vec<Output>,
+}
+
+/// A transaction included in a block.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfirmedTransaction {
+    pub transaction: AuthorizedTransaction,
+    pub height: u32,
+    pub block_hash: BlockHash,
+    pub index: u32,
+    pub confirmations: u32,
+    pub spent_utxos: Vec<Output>,
+}
+
+/// Undo record for a single connected block.
+///
+/// Every map holds the value an entry had before the block was connected,
+/// `None` meaning that the entry did not exist.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct DisconnectData {
-    pub spent_utxos: HashMap<OutPoint, Output>,
-    pub deposits: Vec<OutPoint>,
-    pub pending_bundles: Vec<bitcoin::Txid>,
-    pub spent_bundles: HashMap<bitcoin::Txid, Vec<OutPoint>>,
-    pub spent_withdrawals: HashMap<OutPoint, Output>,
-    pub failed_withdrawals: Vec<bitcoin::Txid>,
+    pub utxos: HashMap<OutPoint, Option<Output>>,
+    pub key_to_value: HashMap<Key, Option<Value>>,
+    pub commitment_to_height: HashMap<Commitment, Option<u32>>,
+    pub commitment_to_outpoint: HashMap<Commitment, Option<OutPoint>>,
+    pub height_to_commitments: HashMap<u32, Option<Vec<Commitment>>>,
+    pub key_to_commitment: HashMap<Key, Option<Commitment>>,
+    pub commitment_to_key: HashMap<Commitment, Option<Key>>,
+    pub key_history: HashMap<(Key, [u8; 4]), Option<Vec<KeyHistoryEntry>>>,
+    pub key_to_records: HashMap<Key, Option<Vec<Record>>>,
+    pub key_to_parent: HashMap<Key, Option<Key>>,
+    pub key_to_subnames: HashMap<Key, Option<Vec<Key>>>,
+    pub key_to_auction: HashMap<Key, Option<Auction>>,
+    pub auction_end_height_to_keys: HashMap<u32, Option<Vec<Key>>>,
+    pub key_to_expiry_height: HashMap<Key, Option<u32>>,
+    pub expiry_height_to_keys: HashMap<u32, Option<Vec<Key>>>,
+    pub withdrawal_bundles: HashMap<bitcoin::Txid, Option<WithdrawalBundleInfo>>,
+    pub outpoint_to_withdrawal_bundle: HashMap<OutPoint, Option<bitcoin::Txid>>,
+    pub withdrawal_statuses: HashMap<OutPoint, Option<WithdrawalStatus>>,
+    pub last_withdrawal_bundle: Option<WithdrawalBundle>,
+    pub last_withdrawal_bundle_failure_height: Option<u32>,
+    pub last_deposit_block: Option<(bitcoin::BlockHash, u32)>,
 }
 
 #[derive(Default)]
@@ -147,6 +382,48 @@ impl TransactionBuilder {
         self.output(address, key_value)
     }
 
+    pub fn set_records(self, address: Address, key: Key, records: Vec<Record>) -> Self {
+        let key_records = BitNamesOutput::KeyRecords { key, records };
+        self.output(address, key_records)
+    }
+
+    pub fn subname(self, address: Address, parent: Key, label: Key) -> Self {
+        let subname = BitNamesOutput::Subname { parent, label };
+        self.output(address, subname)
+    }
+
+    pub fn bid(self, address: Address, key: Key, blinded_bid: Hash, deposit: u64) -> Self {
+        let bid = BitNamesOutput::Bid {
+            key,
+            blinded_bid,
+            deposit,
+        };
+        self.output(address, bid)
+    }
+
+    pub fn reveal_bid(
+        self,
+        address: Address,
+        key: Key,
+        salt: Salt,
+        amount: u64,
+        deposit: u64,
+    ) -> Self {
+        let revealed_bid = BitNamesOutput::RevealedBid {
+            key,
+            salt,
+            amount,
+            deposit,
+        };
+        self.output(address, revealed_bid)
+    }
+
+    /// Move a name to `to_address` without changing its value.
+    pub fn transfer(self, key: Key, to_address: Address) -> TransactionBuilder {
+        let transfer = BitNamesOutput::Transfer { key };
+        self.output(to_address, transfer)
+    }
+
     pub fn build(self) -> Transaction {
         Transaction {
             inputs: self.inputs,
@@ -157,7 +434,11 @@ impl TransactionBuilder {
 
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
 
-#[derive(Eq, PartialEq, Clone, Debug)]
+/// All pending withdrawals to a single mainchain address.
+///
+/// Ordered by mainchain fee, then value, then address. Aggregates are
+/// compared without their spent utxos, as there is only one per address.
+#[derive(Clone, Debug)]
 pub struct AggregatedWithdrawal {
     pub spent_utxos: HashMap<OutPoint, Output>,
     pub main_address: bitcoin::Address,
@@ -167,19 +448,21 @@ pub struct AggregatedWithdrawal {
 
 impl Ord for AggregatedWithdrawal {
     fn cmp(&self, other: &Self) -> Ordering {
-        if self == other {
-            Ordering::Equal
-        } else if self.main_fee > other.main_fee
-            || self.value > other.value
-            || self.main_address > other.main_address
-        {
-            Ordering::Greater
-        } else {
-            Ordering::Less
-        }
+        self.main_fee
+            .cmp(&other.main_fee)
+            .then_with(|| self.value.cmp(&other.value))
+            .then_with(|| self.main_address.cmp(&other.main_address))
     }
 }
 
+impl PartialEq for AggregatedWithdrawal {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for AggregatedWithdrawal {}
+
 impl PartialOrd for AggregatedWithdrawal {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
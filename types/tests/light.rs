@@ -0,0 +1,233 @@
+use bitnames_types::bitcoin::hashes::{Hash as _, HashEngine as _};
+use bitnames_types::light::*;
+use bitnames_types::*;
+
+/// Mainchain built by the test, every block can commit to a sidechain header.
+struct MainChain {
+    blocks: Vec<bitcoin::BlockHash>,
+}
+
+impl MainChain {
+    fn new() -> Self {
+        Self {
+            blocks: vec![bitcoin::BlockHash::from_inner([0xaa; 32])],
+        }
+    }
+
+    fn tip(&self) -> bitcoin::BlockHash {
+        *self.blocks.last().unwrap()
+    }
+
+    /// Mine a block with a coinbase output committing to `block_hash`.
+    fn mine(&mut self, block_hash: &BlockHash) -> BmmProof {
+        let coinbase = bitcoin::Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime(0),
+            input: vec![bitcoin::TxIn {
+                previous_output: bitcoin::OutPoint::null(),
+                script_sig: bitcoin::Script::from(vec![self.blocks.len() as u8]),
+                sequence: bitcoin::Sequence::MAX,
+                witness: bitcoin::Witness::default(),
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 0,
+                script_pubkey: bmm_commitment_script(block_hash),
+            }],
+        };
+        let sibling = bitcoin::TxMerkleNode::from_inner([0xbb; 32]);
+        let main_header = bitcoin::BlockHeader {
+            version: 1,
+            prev_blockhash: self.tip(),
+            merkle_root: merkle_root(&coinbase, &sibling),
+            time: self.blocks.len() as u32,
+            bits: 0,
+            nonce: 0,
+        };
+        self.blocks.push(main_header.block_hash());
+        BmmProof {
+            main_header,
+            coinbase,
+            merkle_branch: vec![sibling],
+        }
+    }
+}
+
+fn merkle_root(
+    coinbase: &bitcoin::Transaction,
+    sibling: &bitcoin::TxMerkleNode,
+) -> bitcoin::TxMerkleNode {
+    let mut engine = bitcoin::TxMerkleNode::engine();
+    engine.input(&coinbase.txid()[..]);
+    engine.input(&sibling[..]);
+    bitcoin::TxMerkleNode::from_engine(engine)
+}
+
+/// Header extending `prev`, mined on the tip of `main_chain`.
+fn header(prev: &Header, main_chain: &MainChain, state_root: Hash) -> Header {
+    Header {
+        prev_side_block_hash: prev.block_hash(),
+        prev_main_block_hash: main_chain.tip(),
+        merkle_root: Default::default(),
+        state_root,
+    }
+}
+
+/// Mine a header extending `prev`.
+fn mine(prev: &Header, main_chain: &mut MainChain, state_root: Hash) -> (Header, BmmProof) {
+    let header = header(prev, main_chain, state_root);
+    let proof = main_chain.mine(&header.block_hash());
+    (header, proof)
+}
+
+/// Root of a state tree with the single name `key`.
+fn single_name_root(key: &Key, value: &Value) -> Hash {
+    let mut node = state_tree_leaf(key, value);
+    for depth in (0..STATE_TREE_DEPTH).rev() {
+        node = if state_tree_bit(key, depth) {
+            state_tree_node(&Hash::default(), &node)
+        } else {
+            state_tree_node(&node, &Hash::default())
+        };
+    }
+    node
+}
+
+#[test]
+fn names_are_resolved_against_bmm_verified_headers() {
+    let mut main_chain = MainChain::new();
+    let mut client = LightClient::new();
+    let key: Key = hash(&"alice").into();
+    let value = Value::from(hash(&"value"));
+    let first = mine(&Header::genesis(), &mut main_chain, Hash::default());
+    let state_root = single_name_root(&key, &value);
+    let second = mine(&first.0, &mut main_chain, state_root);
+    client
+        .connect_headers(&[first, second], &main_chain.blocks)
+        .unwrap();
+    assert_eq!(client.get_best_header().0, 2);
+
+    let proof = NameProof {
+        key,
+        value: Some(value),
+        siblings: vec![None; STATE_TREE_DEPTH],
+    };
+    assert_eq!(client.verify_name(&key, &proof).unwrap(), Some(value));
+    let mut tampered = proof.clone();
+    tampered.value = Some(Value::from([0; 32]));
+    assert!(matches!(
+        client.verify_name(&key, &tampered),
+        Err(Error::InvalidNameProof { .. })
+    ));
+    let other: Key = hash(&"bob").into();
+    assert!(matches!(
+        client.verify_name(&other, &proof),
+        Err(Error::WrongKey { .. })
+    ));
+}
+
+#[test]
+fn forged_headers_are_rejected() {
+    let mut main_chain = MainChain::new();
+    let mut client = LightClient::new();
+    let genesis = Header::genesis();
+
+    // A header that no mainchain block committed to.
+    let (committed, proof) = mine(&genesis, &mut main_chain, Hash::default());
+    let forged = Header {
+        state_root: hash(&"forged"),
+        ..committed.clone()
+    };
+    assert!(matches!(
+        client.connect_headers(&[(forged, proof.clone())], &main_chain.blocks),
+        Err(Error::InvalidBmmProof { height: 1 })
+    ));
+
+    // A header committed in a mainchain block that isn't in the best
+    // mainchain.
+    let mut other_chain = MainChain::new();
+    other_chain.blocks[0] = bitcoin::BlockHash::from_inner([0xcc; 32]);
+    let (header, other_proof) = mine(&genesis, &mut other_chain, Hash::default());
+    let header = Header {
+        prev_main_block_hash: main_chain.blocks[0],
+        ..header
+    };
+    assert!(matches!(
+        client.connect_headers(&[(header, other_proof)], &main_chain.blocks),
+        Err(Error::WrongMainBlock { height: 1, .. })
+    ));
+
+    // Headers must extend a known header.
+    let (orphan, orphan_proof) = mine(&committed, &mut main_chain, Hash::default());
+    assert!(matches!(
+        client.connect_headers(&[(orphan, orphan_proof)], &main_chain.blocks),
+        Err(Error::UnknownPrevSideBlock)
+    ));
+    assert_eq!(client.get_best_header().0, 0);
+    client
+        .connect_headers(&[(committed, proof)], &main_chain.blocks)
+        .unwrap();
+}
+
+#[test]
+fn tampered_bmm_proofs_are_rejected() {
+    let mut main_chain = MainChain::new();
+    let mut client = LightClient::new();
+    let (header, proof) = mine(&Header::genesis(), &mut main_chain, Hash::default());
+
+    // The coinbase commits to another header.
+    let mut other_commitment = proof.clone();
+    other_commitment.coinbase.output[0].script_pubkey =
+        bmm_commitment_script(&Header::genesis().block_hash());
+    // The coinbase isn't in the mainchain block.
+    let mut wrong_branch = proof.clone();
+    wrong_branch.merkle_branch = vec![bitcoin::TxMerkleNode::from_inner([0xdd; 32])];
+    // A transaction that isn't a coinbase.
+    let mut not_coinbase = proof.clone();
+    not_coinbase.coinbase.input[0].previous_output.vout = 0;
+    for tampered in [other_commitment, wrong_branch, not_coinbase] {
+        assert!(matches!(
+            client.connect_headers(&[(header.clone(), tampered)], &main_chain.blocks),
+            Err(Error::InvalidBmmProof { height: 1 })
+        ));
+    }
+    assert_eq!(client.get_best_header().0, 0);
+    client
+        .connect_headers(&[(header, proof)], &main_chain.blocks)
+        .unwrap();
+}
+
+#[test]
+fn later_branches_replace_the_best_chain() {
+    let mut main_chain = MainChain::new();
+    let mut client = LightClient::new();
+    let genesis = Header::genesis();
+    let a1 = mine(&genesis, &mut main_chain, hash(&"a1"));
+    let early = mine(&genesis, &mut main_chain, hash(&"early"));
+    let a2 = mine(&a1.0, &mut main_chain, hash(&"a2"));
+    client
+        .connect_headers(&[a1.clone(), a2.clone()], &main_chain.blocks)
+        .unwrap();
+
+    // A branch mined before the best header doesn't replace it.
+    assert!(matches!(
+        client.connect_headers(&[early], &main_chain.blocks),
+        Err(Error::NotBestChain)
+    ));
+    assert_eq!(client.get_best_header().1.state_root, hash(&"a2"));
+
+    // A branch mined after it does.
+    let b2 = mine(&a1.0, &mut main_chain, hash(&"b2"));
+    let b3 = mine(&b2.0, &mut main_chain, hash(&"b3"));
+    client
+        .connect_headers(&[b2.clone(), b3], &main_chain.blocks)
+        .unwrap();
+    let (height, best) = client.get_best_header();
+    assert_eq!((height, best.state_root), (3, hash(&"b3")));
+    assert_eq!(client.get_header(1).unwrap().state_root, hash(&"a1"));
+
+    // Headers mined on mainchain blocks that were reorged out are dropped.
+    main_chain.blocks.pop();
+    client.connect_headers(&[], &main_chain.blocks).unwrap();
+    let (height, best) = client.get_best_header();
+    assert_eq!((height, best.block_hash()), (2, b2.0.block_hash()));
+}
@@ -1,5 +1,5 @@
 use bitnames_types::{bitcoin, sdk_types};
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Parser)]
 #[clap(author, version, about)]
@@ -16,15 +16,74 @@ pub enum Command {
     Name(Name),
     #[command(subcommand)]
     Address(Address),
+    /// Show whether a withdrawal output is pending, bundled, paid out or
+    /// refunded.
+    WithdrawalStatus {
+        /// Hex encoded id of the transaction that created the withdrawal.
+        txid: String,
+        vout: u32,
+    },
     // #[command(subcommand)]
     // Funds(Funds),
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Name {
-    Commit { name: String },
-    Claim { name: String },
-    Set { name: String, value: String },
+    Commit {
+        name: String,
+    },
+    Claim {
+        name: String,
+    },
+    Set {
+        name: String,
+        value: String,
+    },
+    Renew {
+        name: String,
+    },
+    Transfer {
+        name: String,
+        to: sdk_types::Address,
+    },
+    /// Create the subname `label.parent` of a name owned by this wallet.
+    Subname {
+        parent: String,
+        label: String,
+    },
+    /// Replace records of a given type with a single record.
+    SetRecord {
+        name: String,
+        record_type: RecordType,
+        value: String,
+    },
+    GetRecords {
+        name: String,
+        /// Only show records of this type.
+        #[arg(long)]
+        record_type: Option<RecordType>,
+    },
+    /// Place a sealed bid for a name, locking `deposit` until the auction
+    /// ends.
+    Bid {
+        name: String,
+        amount: bitcoin::Amount,
+        deposit: bitcoin::Amount,
+    },
+    /// Reveal the bid placed for a name.
+    RevealBid {
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RecordType {
+    Ipv4,
+    Ipv6,
+    Txt,
+    Ed25519,
+    MainchainAddress,
+    ContentHash,
 }
 
 #[derive(Debug, Subcommand)]
@@ -4,7 +4,7 @@ mod state;
 mod wallet;
 
 use anyhow::Result;
-use args::{Address, Cli, Command, Name};
+use args::{Address, Cli, Command, Name, RecordType};
 use bitcoin::hashes::{sha256, Hash};
 use bitnames_types::*;
 use clap::Parser;
@@ -31,6 +31,46 @@ fn address(command: Address, wallet: &mut Wallet) -> Result<()> {
     Ok(())
 }
 
+/// Get the key of a name, `label.parent` is the subname `label` of `parent`.
+fn name_key(name: &str) -> Key {
+    match name.split_once('.') {
+        Some((label, parent)) => {
+            let label: Key = sdk_types::hash(&label).into();
+            subname_key(&name_key(parent), &label)
+        }
+        None => sdk_types::hash(&name).into(),
+    }
+}
+
+fn parse_record(record_type: RecordType, value: &str) -> Result<Record> {
+    let record = match record_type {
+        RecordType::Ipv4 => Record::Ipv4(value.parse()?),
+        RecordType::Ipv6 => Record::Ipv6(value.parse()?),
+        RecordType::Txt => Record::Txt(value.into()),
+        RecordType::Ed25519 => Record::Ed25519PublicKey(parse_bytes(value)?),
+        RecordType::MainchainAddress => Record::MainchainAddress(value.parse()?),
+        RecordType::ContentHash => Record::ContentHash(parse_bytes(value)?),
+    };
+    Ok(record)
+}
+
+fn parse_bytes(value: &str) -> Result<[u8; 32]> {
+    hex::decode(value)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected 32 hex encoded bytes"))
+}
+
+fn get_record_type(record: &Record) -> RecordType {
+    match record {
+        Record::Ipv4(_) => RecordType::Ipv4,
+        Record::Ipv6(_) => RecordType::Ipv6,
+        Record::Txt(_) => RecordType::Txt,
+        Record::Ed25519PublicKey(_) => RecordType::Ed25519,
+        Record::MainchainAddress(_) => RecordType::MainchainAddress,
+        Record::ContentHash(_) => RecordType::ContentHash,
+    }
+}
+
 async fn name(command: Name, wallet: &mut Wallet) -> Result<()> {
     match command {
         Name::Commit { name } => {
@@ -42,10 +82,52 @@ async fn name(command: Name, wallet: &mut Wallet) -> Result<()> {
             wallet.claim(&key).await?;
         }
         Name::Set { name, value } => {
-            let key: Key = sdk_types::hash(&name).into();
+            let key = name_key(&name);
             let value: Value = sdk_types::hash(&value).into();
             wallet.set(&key, &value).await?;
         }
+        Name::Renew { name } => {
+            let key = name_key(&name);
+            wallet.renew(&key).await?;
+        }
+        Name::Transfer { name, to } => {
+            let key = name_key(&name);
+            wallet.transfer(&key, &to).await?;
+        }
+        Name::Subname { parent, label } => {
+            let parent = name_key(&parent);
+            let label: Key = sdk_types::hash(&label).into();
+            wallet.create_subname(&parent, &label).await?;
+        }
+        Name::SetRecord {
+            name,
+            record_type,
+            value,
+        } => {
+            let key = name_key(&name);
+            let record = parse_record(record_type, &value)?;
+            wallet.set_record(&key, record).await?;
+        }
+        Name::GetRecords { name, record_type } => {
+            let key = name_key(&name);
+            for record in wallet.get_records(&key).await? {
+                if record_type.map_or(true, |record_type| record_type == get_record_type(&record)) {
+                    println!("{:?}", record);
+                }
+            }
+        }
+        Name::Bid {
+            name,
+            amount,
+            deposit,
+        } => {
+            let key = name_key(&name);
+            wallet.bid(&key, amount.to_sat(), deposit.to_sat()).await?;
+        }
+        Name::RevealBid { name } => {
+            let key = name_key(&name);
+            wallet.reveal_bid(&key).await?;
+        }
     }
     Ok(())
 }
@@ -68,6 +150,14 @@ async fn main() -> Result<()> {
         Command::Name(command) => name(command, &mut wallet).await?,
         Command::Address(command) => address(command, &mut wallet)?,
         Command::Update => wallet.update().await?,
+        Command::WithdrawalStatus { txid, vout } => {
+            let txid = Txid::from(parse_bytes(&txid)?);
+            let outpoint = OutPoint::Regular { txid, vout };
+            match wallet.get_withdrawal_status(&outpoint).await? {
+                Some(status) => println!("{:?}", status),
+                None => println!("unknown withdrawal"),
+            }
+        }
         Command::Balance => {
             let balance = wallet.get_balance()?;
             let balance = bitcoin::Amount::from_sat(balance);
@@ -6,19 +6,23 @@ pub struct State {
     key_to_outpoint: Database<SerdeBincode<Key>, SerdeBincode<OutPoint>>,
     outpoint_to_address: Database<SerdeBincode<OutPoint>, SerdeBincode<Address>>,
     utxos: Database<SerdeBincode<OutPoint>, SerdeBincode<Output>>,
+    /// Amount and deposit of sealed bids placed by this wallet.
+    key_to_bid: Database<SerdeBincode<Key>, SerdeBincode<(u64, u64)>>,
 }
 
 impl State {
-    pub const NUM_DBS: u32 = 3;
+    pub const NUM_DBS: u32 = 4;
 
     pub fn new(env: &heed::Env) -> Result<Self, Error> {
         let utxos = env.create_database(Some("utxos"))?;
         let outpoint_to_address = env.create_database(Some("outpoint_to_address"))?;
         let key_to_outpoint = env.create_database(Some("key_to_outpoint"))?;
+        let key_to_bid = env.create_database(Some("key_to_bid"))?;
         Ok(Self {
             outpoint_to_address,
             key_to_outpoint,
             utxos,
+            key_to_bid,
         })
     }
 
@@ -45,6 +49,14 @@ impl State {
             })?)
     }
 
+    pub fn get_utxo(&self, txn: &RoTxn, outpoint: &OutPoint) -> Result<Output, Error> {
+        self.utxos
+            .get(txn, outpoint)?
+            .ok_or(Error::NoUtxoForOutPoint {
+                outpoint: *outpoint,
+            })
+    }
+
     pub fn get_key_outpoint(&self, txn: &RoTxn, key: &Key) -> Result<OutPoint, Error> {
         self.key_to_outpoint
             .get(txn, key)?
@@ -61,6 +73,54 @@ impl State {
         Ok(())
     }
 
+    pub fn delete_key_outpoint(&self, txn: &mut RwTxn, key: &Key) -> Result<(), Error> {
+        self.key_to_outpoint.delete(txn, key)?;
+        Ok(())
+    }
+
+    pub fn get_bid(&self, txn: &RoTxn, key: &Key) -> Result<(u64, u64), Error> {
+        self.key_to_bid
+            .get(txn, key)?
+            .ok_or(Error::NoBidForKey { key: *key })
+    }
+
+    pub fn set_bid(
+        &self,
+        txn: &mut RwTxn,
+        key: &Key,
+        amount: u64,
+        deposit: u64,
+    ) -> Result<(), Error> {
+        self.key_to_bid.put(txn, key, &(amount, deposit))?;
+        Ok(())
+    }
+
+    pub fn delete_bid(&self, txn: &mut RwTxn, key: &Key) -> Result<(), Error> {
+        self.key_to_bid.delete(txn, key)?;
+        Ok(())
+    }
+
+    /// Select value outputs worth at least `value`, returns them with their
+    /// total value.
+    pub fn select_coins(&self, txn: &RoTxn, value: u64) -> Result<(Vec<OutPoint>, u64), Error> {
+        let mut selected = vec![];
+        let mut total = 0;
+        for item in self.utxos.iter(txn)? {
+            if total >= value {
+                break;
+            }
+            let (outpoint, output) = item?;
+            if let Content::Value(output_value) = output.content {
+                selected.push(outpoint);
+                total += output_value;
+            }
+        }
+        if total < value {
+            return Err(Error::NotEnoughFunds { value, total });
+        }
+        Ok((selected, total))
+    }
+
     pub fn get_balance(&self, txn: &RoTxn) -> Result<u64, Error> {
         let mut balance = 0;
         for item in self.utxos.iter(txn)? {
@@ -73,6 +133,8 @@ impl State {
     pub fn add_utxos(&self, txn: &mut RwTxn, utxos: &[(OutPoint, Output)]) -> Result<(), Error> {
         for (outpoint, output) in utxos {
             self.utxos.put(txn, outpoint, output)?;
+            self.outpoint_to_address
+                .put(txn, outpoint, &output.address)?;
         }
         Ok(())
     }
@@ -107,4 +169,10 @@ pub enum Error {
     NoAddressForOutPoint { outpoint: OutPoint },
     #[error("no outpoint for key {key}")]
     NoOutPointForKey { key: Key },
+    #[error("no utxo for outpoint {outpoint}")]
+    NoUtxoForOutPoint { outpoint: OutPoint },
+    #[error("no bid for key {key}")]
+    NoBidForKey { key: Key },
+    #[error("not enough funds {total} < {value}")]
+    NotEnoughFunds { value: u64, total: u64 },
 }
@@ -129,10 +129,11 @@ impl Wallet {
         dbg!(&outpoint);
         let address = self.state.get_outpoint_address(&wtxn, &outpoint)?;
         let salt = self.signer.salt(&wtxn, &address, key)?;
-        let transaction = TransactionBuilder::default()
+        let builder = TransactionBuilder::default()
             .spend(outpoint)
-            .reveal(address, *key, salt)
-            .build();
+            .reveal(address, *key, salt);
+        let registration_fee = ConsensusParams::default().registration_fee;
+        let transaction = self.fund(&mut wtxn, builder, registration_fee)?.build();
         let outpoint = OutPoint::Regular {
             txid: transaction.txid(),
             vout: 0,
@@ -148,6 +149,26 @@ impl Wallet {
         Ok(())
     }
 
+    /// Spend value outputs worth `value` in `builder`, sending the change to
+    /// a new address.
+    fn fund(
+        &self,
+        wtxn: &mut RwTxn,
+        builder: TransactionBuilder,
+        value: u64,
+    ) -> Result<TransactionBuilder, Error> {
+        let (inputs, total) = self.state.select_coins(wtxn, value)?;
+        let mut builder = builder;
+        for outpoint in inputs {
+            builder = builder.spend(outpoint);
+        }
+        if total > value {
+            let change_address = self.signer.get_new_address(wtxn)?;
+            builder = builder.value(change_address, total - value);
+        }
+        Ok(builder)
+    }
+
     pub fn list(&self) -> Result<Vec<Key>, Error> {
         todo!();
     }
@@ -174,6 +195,179 @@ impl Wallet {
         wtxn.commit()?;
         Ok(())
     }
+
+    pub async fn get_records(&mut self, key: &Key) -> Result<Vec<Record>, Error> {
+        let key = bincode::serialize(key)?;
+        let request = tonic::Request::new(GetRecordsRequest { key });
+        let response = self.client.get_records(request).await?;
+        let records = response
+            .into_inner()
+            .records
+            .iter()
+            .map(|record| Ok(bincode::deserialize(record)?))
+            .collect::<Result<_, Error>>()?;
+        Ok(records)
+    }
+
+    pub async fn get_withdrawal_status(
+        &mut self,
+        outpoint: &OutPoint,
+    ) -> Result<Option<WithdrawalStatus>, Error> {
+        let outpoint = bincode::serialize(outpoint)?;
+        let request = tonic::Request::new(GetWithdrawalStatusRequest { outpoint });
+        let response = self.client.get_withdrawal_status(request).await?;
+        let status = bincode::deserialize(&response.into_inner().status)?;
+        Ok(status)
+    }
+
+    /// Replace records of the same type as `record` with `record`.
+    pub async fn set_record(&mut self, key: &Key, record: Record) -> Result<(), Error> {
+        let mut records = self.get_records(key).await?;
+        records.retain(|other| std::mem::discriminant(other) != std::mem::discriminant(&record));
+        records.push(record);
+        let mut wtxn = self.env.write_txn()?;
+        let outpoint = self.state.get_key_outpoint(&wtxn, key)?;
+        let address = self.state.get_outpoint_address(&wtxn, &outpoint)?;
+        let transaction = TransactionBuilder::default()
+            .spend(outpoint)
+            .set_records(address, *key, records)
+            .build();
+        let outpoint = OutPoint::Regular {
+            txid: transaction.txid(),
+            vout: 0,
+        };
+        self.state.set_key_outpoint(&mut wtxn, key, &outpoint)?;
+        let transaction = self.authorize(&wtxn, transaction)?;
+        self.state.connect(&mut wtxn, &transaction)?;
+        let transaction = bincode::serialize(&transaction)?;
+        let request = tonic::Request::new(SubmitTransactionRequest { transaction });
+        self.client.submit_transaction(request).await?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    pub async fn create_subname(&mut self, parent: &Key, label: &Key) -> Result<(), Error> {
+        let mut wtxn = self.env.write_txn()?;
+        let parent_outpoint = self.state.get_key_outpoint(&wtxn, parent)?;
+        let parent_address = self.state.get_outpoint_address(&wtxn, &parent_outpoint)?;
+        let address = self.signer.get_new_address(&mut wtxn)?;
+        // Parent name is spent to authorize the subname, so send it back.
+        let transaction = TransactionBuilder::default()
+            .spend(parent_outpoint)
+            .transfer(*parent, parent_address)
+            .subname(address, *parent, *label)
+            .build();
+        let txid = transaction.txid();
+        self.state
+            .set_key_outpoint(&mut wtxn, parent, &OutPoint::Regular { txid, vout: 0 })?;
+        let key = subname_key(parent, label);
+        self.state
+            .set_key_outpoint(&mut wtxn, &key, &OutPoint::Regular { txid, vout: 1 })?;
+        let transaction = self.authorize(&wtxn, transaction)?;
+        self.state.connect(&mut wtxn, &transaction)?;
+        let transaction = bincode::serialize(&transaction)?;
+        let request = tonic::Request::new(SubmitTransactionRequest { transaction });
+        self.client.submit_transaction(request).await?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Place a sealed bid of `amount` for a name, locking `deposit`.
+    pub async fn bid(&mut self, key: &Key, amount: u64, deposit: u64) -> Result<(), Error> {
+        let mut wtxn = self.env.write_txn()?;
+        let address = self.signer.get_new_address(&mut wtxn)?;
+        let salt = self.signer.salt(&wtxn, &address, key)?;
+        let blinded_bid = blind_bid(key, amount, &salt);
+        let builder = TransactionBuilder::default().bid(address, *key, blinded_bid, deposit);
+        let transaction = self.fund(&mut wtxn, builder, deposit)?.build();
+        let outpoint = OutPoint::Regular {
+            txid: transaction.txid(),
+            vout: 0,
+        };
+        self.state.set_key_outpoint(&mut wtxn, key, &outpoint)?;
+        self.state.set_bid(&mut wtxn, key, amount, deposit)?;
+        let transaction = self.authorize(&wtxn, transaction)?;
+        self.state.connect(&mut wtxn, &transaction)?;
+        let transaction = bincode::serialize(&transaction)?;
+        let request = tonic::Request::new(SubmitTransactionRequest { transaction });
+        self.client.submit_transaction(request).await?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Reveal the sealed bid placed for a name once bidding is closed.
+    pub async fn reveal_bid(&mut self, key: &Key) -> Result<(), Error> {
+        let mut wtxn = self.env.write_txn()?;
+        let outpoint = self.state.get_key_outpoint(&wtxn, key)?;
+        let address = self.state.get_outpoint_address(&wtxn, &outpoint)?;
+        let salt = self.signer.salt(&wtxn, &address, key)?;
+        let (amount, deposit) = self.state.get_bid(&wtxn, key)?;
+        let transaction = TransactionBuilder::default()
+            .spend(outpoint)
+            .reveal_bid(address, *key, salt, amount, deposit)
+            .build();
+        let outpoint = OutPoint::Regular {
+            txid: transaction.txid(),
+            vout: 0,
+        };
+        self.state.set_key_outpoint(&mut wtxn, key, &outpoint)?;
+        self.state.delete_bid(&mut wtxn, key)?;
+        let transaction = self.authorize(&wtxn, transaction)?;
+        self.state.connect(&mut wtxn, &transaction)?;
+        let transaction = bincode::serialize(&transaction)?;
+        let request = tonic::Request::new(SubmitTransactionRequest { transaction });
+        self.client.submit_transaction(request).await?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    pub async fn transfer(&mut self, key: &Key, to: &Address) -> Result<(), Error> {
+        let mut wtxn = self.env.write_txn()?;
+        let outpoint = self.state.get_key_outpoint(&wtxn, key)?;
+        let transaction = TransactionBuilder::default()
+            .spend(outpoint)
+            .transfer(*key, *to)
+            .build();
+        self.state.delete_key_outpoint(&mut wtxn, key)?;
+        let transaction = self.authorize(&wtxn, transaction)?;
+        self.state.connect(&mut wtxn, &transaction)?;
+        let transaction = bincode::serialize(&transaction)?;
+        let request = tonic::Request::new(SubmitTransactionRequest { transaction });
+        self.client.submit_transaction(request).await?;
+        wtxn.commit()?;
+        Ok(())
+    }
+
+    /// Transfer a name to its current address, so that it doesn't expire.
+    pub async fn renew(&mut self, key: &Key) -> Result<(), Error> {
+        let mut wtxn = self.env.write_txn()?;
+        let outpoint = self.state.get_key_outpoint(&wtxn, key)?;
+        let address = self.state.get_outpoint_address(&wtxn, &outpoint)?;
+        match self.state.get_utxo(&wtxn, &outpoint)?.content {
+            Content::Custom(BitNamesOutput::Reveal { .. })
+            | Content::Custom(BitNamesOutput::KeyValue { .. })
+            | Content::Custom(BitNamesOutput::Transfer { .. })
+            | Content::Custom(BitNamesOutput::KeyRecords { .. })
+            | Content::Custom(BitNamesOutput::Subname { .. }) => {}
+            _ => return Err(Error::NotAName { outpoint }),
+        }
+        let transaction = TransactionBuilder::default()
+            .spend(outpoint)
+            .transfer(*key, address)
+            .build();
+        let outpoint = OutPoint::Regular {
+            txid: transaction.txid(),
+            vout: 0,
+        };
+        self.state.set_key_outpoint(&mut wtxn, key, &outpoint)?;
+        let transaction = self.authorize(&wtxn, transaction)?;
+        self.state.connect(&mut wtxn, &transaction)?;
+        let transaction = bincode::serialize(&transaction)?;
+        let request = tonic::Request::new(SubmitTransactionRequest { transaction });
+        self.client.submit_transaction(request).await?;
+        wtxn.commit()?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -192,4 +386,6 @@ pub enum Error {
     Bincode(#[from] bincode::Error),
     #[error("rpc error")]
     Rpc(#[from] tonic::Status),
+    #[error("outpoint {outpoint} is not a name output")]
+    NotAName { outpoint: OutPoint },
 }